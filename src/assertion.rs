@@ -0,0 +1,256 @@
+use crate::{Amount, Balance, Journal, Posting, Transaction};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Running balances per account, built up one posting at a time.
+#[derive(Debug, Default)]
+pub(crate) struct Running<'a> {
+    accounts: BTreeMap<&'a str, Balance<'a>>,
+}
+
+impl<'a> Running<'a> {
+    /// Adds the posting's amount to its account, or returns `None` if the
+    /// balance would get too large to represent.
+    pub(crate) fn post(&mut self, posting: &Posting<'a>) -> Option<()> {
+        match &posting.amount {
+            Some(amount) => self
+                .accounts
+                .entry(posting.account.name)
+                .or_default()
+                .checked_add(amount),
+            None => Some(()),
+        }
+    }
+
+    /// The balance of `account`, including its subaccounts if `inclusive`, or
+    /// `None` if it is too large to represent.
+    fn balance(&self, account: &str, inclusive: bool) -> Option<Balance<'a>> {
+        let mut total = Balance::default();
+        for (name, balance) in &self.accounts {
+            let included = *name == account
+                || inclusive
+                    && name
+                        .strip_prefix(account)
+                        .is_some_and(|sub| sub.starts_with(':'));
+            if included {
+                for amount in balance.amounts() {
+                    total.checked_add(&amount)?;
+                }
+            }
+        }
+        Some(total)
+    }
+
+    /// Fills in the amounts of the transaction's balance assignments, so that
+    /// each brings its account to the assigned balance. Postings earlier in
+    /// the transaction count towards the balance being assigned. Returns
+    /// `None` if a balance or an assigned amount is too large to represent.
+    pub(crate) fn assign(&self, transaction: &mut Transaction<'a>) -> Option<()> {
+        let mut earlier = Running::default();
+        for posting in &mut transaction.postings {
+            let assignment = posting
+                .assertion
+                .as_ref()
+                .filter(|_| posting.amount.is_none());
+            if let Some(assertion) = assignment {
+                let mut current = Decimal::ZERO;
+                for running in [self, &earlier] {
+                    let balance = running.balance(posting.account.name, assertion.inclusive)?;
+                    current = current.checked_add(balance.get(assertion.amount.currency))?;
+                }
+                posting.amount = Some(Amount {
+                    amount: assertion.amount.amount.checked_sub(current)?,
+                    ..assertion.amount.clone()
+                });
+            }
+            earlier.post(posting)?;
+        }
+        Some(())
+    }
+}
+
+/// A balance assertion that did not hold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionError<'a> {
+    pub file: Option<&'a Path>,
+    /// 1-based line of the posting with the assertion.
+    pub line: usize,
+    pub account: &'a str,
+    pub expected: Amount<'a>,
+    /// The account's balance in the asserted commodity, or all of it for a
+    /// total (`==`) assertion. `None` if it is too large to represent.
+    pub actual: Option<Balance<'a>>,
+}
+
+impl fmt::Display for AssertionError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: balance assertion failed for {}: expected {} {}, but the balance is ",
+            self.line, self.account, self.expected.currency, self.expected.amount
+        )?;
+        match &self.actual {
+            Some(actual) => write!(f, "{}", actual),
+            None => write!(f, "too large to represent"),
+        }
+    }
+}
+
+impl std::error::Error for AssertionError<'_> {}
+
+impl<'a> Journal<'a> {
+    /// Transaction indices in date order, keeping file order within a day.
+    pub(crate) fn date_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.transactions.len()).collect();
+        order.sort_by_key(|&i| self.transactions[i].date);
+        order
+    }
+
+    /// Walks the transactions in date order and checks every balance
+    /// assertion against the running balance of its account. Run this after
+    /// [`Journal::balance`], so elided and assigned amounts are known.
+    pub fn check_assertions(&self) -> Result<(), Vec<AssertionError<'a>>> {
+        let mut running = Running::default();
+        let mut errors = Vec::new();
+        for i in self.date_order() {
+            let transaction = &self.transactions[i];
+            for posting in &transaction.postings {
+                // Sums too large to represent are reported by `Journal::balance`.
+                let _ = running.post(posting);
+                let Some(assertion) = &posting.assertion else {
+                    continue;
+                };
+                let expected = &assertion.amount;
+                let error = |actual| AssertionError {
+                    file: transaction.file,
+                    line: posting.line,
+                    account: posting.account.name,
+                    expected: expected.clone(),
+                    actual,
+                };
+                let Some(balance) = running.balance(posting.account.name, assertion.inclusive)
+                else {
+                    errors.push(error(None));
+                    continue;
+                };
+                let held = balance.get(expected.currency);
+                let others = balance.amounts().any(|a| a.currency != expected.currency);
+                if held == expected.amount && !(assertion.total && others) {
+                    continue;
+                }
+                let actual = match assertion.total {
+                    true => balance,
+                    false => {
+                        let mut actual = Balance::default();
+                        actual.add(&Amount::new(expected.currency, held));
+                        actual
+                    }
+                };
+                errors.push(error(Some(actual)));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse, Amount};
+    use rust_decimal::Decimal;
+
+    const JOURNAL: &str = "2024-03-02 Groceries
+\tExpenses:Food  USD 40
+\tAssets:Checking  = USD 960
+
+2024-03-01 Opening balance
+\tAssets:Checking  USD 1000
+\tEquity:Opening
+
+2024-03-03 Reconcile
+\tAssets:Checking  USD 0 = USD 960
+\tAssets:Savings:Emergency  USD 500
+\tAssets:Checking  USD -500 =* USD 460
+\tAssets  USD 0 =* USD 960
+";
+
+    #[test]
+    fn assignment() {
+        let mut journal = parse(JOURNAL).unwrap();
+        journal.balance().unwrap();
+        assert_eq!(
+            journal.transactions[0].postings[1]
+                .amount
+                .as_ref()
+                .unwrap()
+                .amount,
+            Decimal::new(-40, 0)
+        );
+        journal.check_assertions().unwrap();
+    }
+
+    #[test]
+    fn assignment_after_posting_to_same_account() {
+        let mut journal = parse("2024-03-01 Opening\n\tAssets:Checking  USD 1000\n\tEquity:Opening\n\n2024-03-02 Close\n\tAssets:Checking  USD -100\n\tAssets:Checking  = USD 0\n\tEquity:Closing\n").unwrap();
+        journal.balance().unwrap();
+        assert_eq!(
+            journal.transactions[1].postings[1].amount,
+            Some(Amount::new("USD", Decimal::new(-900, 0)))
+        );
+        journal.check_assertions().unwrap();
+    }
+
+    #[test]
+    fn failed_assertions() {
+        let source = JOURNAL.replace("USD 0 = USD 960", "USD 0 = USD 950");
+        let source = source.replace("=* USD 960", "=* USD 900");
+        let mut journal = parse(&source).unwrap();
+        journal.balance().unwrap();
+        let errors = journal.check_assertions().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                "line 10: balance assertion failed for Assets:Checking: expected USD 950, but the balance is USD 960",
+                "line 13: balance assertion failed for Assets: expected USD 900, but the balance is USD 960",
+            ]
+        );
+    }
+
+    #[test]
+    fn total_assertion() {
+        let mut journal = parse(
+            "2024-03-01 Opening\n\tAssets:Cash  USD 10\n\tAssets:Cash  EUR 5\n\tEquity\n\n2024-03-02 Check\n\tAssets:Cash  USD 0 == USD 10\n",
+        )
+        .unwrap();
+        assert!(journal.balance().is_err());
+        let errors = journal.check_assertions().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 7: balance assertion failed for Assets:Cash: expected USD 10, but the balance is EUR 5, USD 10"
+        );
+    }
+
+    #[test]
+    fn overflowing_balances() {
+        let half = "USD 50000000000000000000000000000";
+        let source = format!("2024-03-01 Deposit\n\tAssets:A  {half}\n\tIncome:A\n\n2024-03-02 Deposit\n\tAssets:B  {half}\n\tIncome:B\n\n2024-03-03 Check\n\tAssets  USD 0 =* USD 1\n");
+        let mut journal = parse(&source).unwrap();
+        journal.balance().unwrap();
+        let errors = journal.check_assertions().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 10: balance assertion failed for Assets: expected USD 1, but the balance is too large to represent"
+        );
+        let source = format!("2024-03-01 Withdraw\n\tAssets:Cash  USD -50000000000000000000000000000\n\tIncome\n\n2024-03-02 Assign\n\tAssets:Cash  = {half}\n\tIncome\n");
+        let mut journal = parse(&source).unwrap();
+        let errors = journal.balance().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec!["line 5: transaction on 2024-03-02 has amounts too large to add up"]
+        );
+    }
+}
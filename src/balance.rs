@@ -0,0 +1,441 @@
+use crate::assertion::Running;
+use crate::{Amount, Journal, Posting, PostingKind, Transaction};
+use chrono::NaiveDate;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// A sum of amounts in any number of commodities.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Balance<'a> {
+    totals: BTreeMap<&'a str, Decimal>,
+}
+
+impl<'a> Balance<'a> {
+    /// Adds `amount`. Panics if the total gets too large to represent; see
+    /// [`Balance::checked_add`].
+    pub fn add(&mut self, amount: &Amount<'a>) {
+        *self.totals.entry(amount.currency).or_default() += amount.amount;
+    }
+
+    /// Adds `amount`, or returns `None` and leaves the balance as it was if
+    /// the total would be too large to represent.
+    pub fn checked_add(&mut self, amount: &Amount<'a>) -> Option<()> {
+        let total = self.totals.entry(amount.currency).or_default();
+        *total = total.checked_add(amount.amount)?;
+        Some(())
+    }
+
+    pub fn get(&self, currency: &str) -> Decimal {
+        self.totals.get(currency).copied().unwrap_or_default()
+    }
+
+    /// Each total rounded to `precision(commodity)` decimal places.
+    pub(crate) fn rounded(&self, precision: impl Fn(&str) -> u32) -> Balance<'a> {
+        let totals = self.totals.iter().map(|(&currency, total)| {
+            let strategy = RoundingStrategy::MidpointAwayFromZero;
+            (
+                currency,
+                total.round_dp_with_strategy(precision(currency), strategy),
+            )
+        });
+        Balance {
+            totals: totals.collect(),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.totals.values().all(Decimal::is_zero)
+    }
+
+    /// The non-zero totals, ordered by commodity.
+    pub fn amounts(&self) -> impl Iterator<Item = Amount<'a>> + '_ {
+        self.totals
+            .iter()
+            .filter(|(_, amount)| !amount.is_zero())
+            .map(|(&currency, &amount)| Amount::new(currency, amount))
+    }
+}
+
+impl fmt::Display for Balance<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut amounts = self.amounts().peekable();
+        if amounts.peek().is_none() {
+            return write!(f, "0");
+        }
+        for (i, amount) in amounts.enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", amount.currency, amount.amount)?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a transaction's postings do not sum to zero.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Imbalance<'a> {
+    /// Every posting has an amount but the total is not zero.
+    Unbalanced(Balance<'a>),
+    /// More than one posting has no amount, so neither can be inferred.
+    MultipleElided,
+    /// The posting without an amount would need one in several commodities.
+    AmbiguousElided(Balance<'a>),
+    /// An amount at cost, or the sum of the amounts, is too large to
+    /// represent.
+    Overflow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceError<'a> {
+    pub file: Option<&'a Path>,
+    pub line: usize,
+    pub date: NaiveDate,
+    pub imbalance: Imbalance<'a>,
+}
+
+impl fmt::Display for BalanceError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: transaction on {} ", self.line, self.date)?;
+        match &self.imbalance {
+            Imbalance::Unbalanced(remainder) => {
+                write!(f, "does not balance, off by {}", remainder)
+            }
+            Imbalance::MultipleElided => {
+                write!(f, "has more than one posting without an amount")
+            }
+            Imbalance::AmbiguousElided(remainder) => write!(
+                f,
+                "cannot infer the missing amount, {} are left over",
+                remainder
+            ),
+            Imbalance::Overflow => write!(f, "has amounts too large to add up"),
+        }
+    }
+}
+
+impl std::error::Error for BalanceError<'_> {}
+
+/// A report total, or a cost being valued, too large to represent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "amounts too large to add up")
+    }
+}
+
+impl std::error::Error for Overflow {}
+
+impl<'a> Posting<'a> {
+    pub fn is_virtual(&self) -> bool {
+        self.kind != PostingKind::Real
+    }
+
+    /// What the posting contributes to its transaction's balance: its `@`
+    /// cost, or else its lot's cost, so trades between commodities balance,
+    /// or else just its amount. `None` if it has no amount, or if its cost is
+    /// too large to represent.
+    pub fn weight(&self) -> Option<Amount<'a>> {
+        let amount = self.amount.as_ref()?;
+        let lot_cost = self.lot.as_ref().and_then(|lot| lot.cost.as_ref());
+        match self.cost.as_ref().or(lot_cost) {
+            Some(cost) => cost.total(amount),
+            None => Some(amount.clone()),
+        }
+    }
+}
+
+/// Raises the precision recorded for `amount`'s commodity to its own.
+fn widen<'a>(precisions: &mut BTreeMap<&'a str, u32>, amount: &Amount<'a>) {
+    let precision = precisions.entry(amount.currency).or_default();
+    *precision = amount.style.precision.max(*precision);
+}
+
+impl<'a> Transaction<'a> {
+    /// Checks that the postings sum to zero, weighing postings at cost where
+    /// one is given, and fills in the amount of the posting that has none.
+    /// Real and `[balanced virtual]` postings must each balance on their own;
+    /// `(virtual)` postings are left alone.
+    pub fn balance(&mut self) -> Result<(), BalanceError<'a>> {
+        self.infer_elided().map_err(|imbalance| BalanceError {
+            file: self.file,
+            line: self.line,
+            date: self.date,
+            imbalance,
+        })
+    }
+
+    fn infer_elided(&mut self) -> Result<(), Imbalance<'a>> {
+        self.infer_elided_of(PostingKind::Real)?;
+        self.infer_elided_of(PostingKind::BalancedVirtual)
+    }
+
+    fn infer_elided_of(&mut self, kind: PostingKind) -> Result<(), Imbalance<'a>> {
+        let mut total = Balance::default();
+        // The style each commodity was first written in, for the inferred
+        // amount.
+        let mut styles = BTreeMap::new();
+        // The most decimal places written in each commodity, in amounts and
+        // in costs.
+        let mut written: BTreeMap<&str, u32> = BTreeMap::new();
+        let mut costs: BTreeMap<&str, u32> = BTreeMap::new();
+        let mut elided = None;
+        let postings = self.postings.iter().enumerate();
+        for (i, posting) in postings.filter(|(_, p)| p.kind == kind) {
+            match posting.weight() {
+                Some(weight) => {
+                    styles.entry(weight.currency).or_insert(weight.style);
+                    if let Some(amount) = &posting.amount {
+                        widen(&mut written, amount);
+                    }
+                    widen(&mut costs, &weight);
+                    total.checked_add(&weight).ok_or(Imbalance::Overflow)?
+                }
+                None if posting.amount.is_some() => return Err(Imbalance::Overflow),
+                None if elided.is_none() => elided = Some(i),
+                None => return Err(Imbalance::MultipleElided),
+            }
+        }
+        // What is left below the precision of the amounts is rounding, as in
+        // `3 VTI @ 33.3333 USD` paid with `100.00 USD`. Costs only set the
+        // precision of commodities no amount is written in.
+        let rounded = total.rounded(|currency| {
+            let precision = written.get(currency).or(costs.get(currency));
+            precision.copied().unwrap_or_default()
+        });
+        let Some(elided) = elided else {
+            return match rounded.is_zero() {
+                true => Ok(()),
+                false => Err(Imbalance::Unbalanced(total)),
+            };
+        };
+        let remainder: Vec<_> = rounded.amounts().collect();
+        match remainder.as_slice() {
+            [amount] => {
+                self.postings[elided].amount = Some(Amount {
+                    currency: amount.currency,
+                    amount: -total.get(amount.currency),
+                    style: styles[amount.currency],
+                });
+                Ok(())
+            }
+            // Everything already balances, so there is nothing to infer.
+            [] => Ok(()),
+            _ => Err(Imbalance::AmbiguousElided(total)),
+        }
+    }
+}
+
+impl<'a> Journal<'a> {
+    /// Balances every transaction, see [`Transaction::balance`]. Transactions
+    /// are processed in date order, so that balance assignments can be
+    /// resolved against the running balances of their accounts first.
+    pub fn balance(&mut self) -> Result<(), Vec<BalanceError<'a>>> {
+        let mut running = Running::default();
+        let mut errors = Vec::new();
+        for i in self.date_order() {
+            let transaction = &mut self.transactions[i];
+            let overflow = BalanceError {
+                file: transaction.file,
+                line: transaction.line,
+                date: transaction.date,
+                imbalance: Imbalance::Overflow,
+            };
+            let balanced = match running.assign(transaction) {
+                Some(()) => transaction.balance(),
+                None => Err(overflow.clone()),
+            };
+            let posted = transaction
+                .postings
+                .iter()
+                .try_for_each(|posting| running.post(posting));
+            match (balanced, posted) {
+                (Err(e), _) => errors.push(e),
+                (Ok(()), None) => errors.push(overflow),
+                (Ok(()), Some(())) => {}
+            }
+        }
+        errors.sort_by_key(|e| (e.file, e.line));
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse, transaction};
+
+    fn balanced(input: &str) -> Result<Transaction<'_>, Imbalance<'_>> {
+        let (_, mut t) = transaction(input).unwrap();
+        t.balance().map(|_| t).map_err(|e| e.imbalance)
+    }
+
+    #[test]
+    fn infer_elided_amount() {
+        let t = balanced("2024-03-01 Shop\n\tExpenses:Food  USD20.00\n\tExpenses:Drink  USD5\n\tLiabilities:Credit").unwrap();
+        assert_eq!(
+            t.postings[2].amount,
+            Some(Amount {
+                currency: "USD",
+                amount: Decimal::new(-2500, 2),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn balanced_transaction() {
+        assert!(balanced(
+            "2024-03-01 Shop\n\tExpenses:Food  USD 1,020.50\n\tLiabilities:Credit  -1020.5 USD"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn balance_at_cost() {
+        assert!(balanced(
+            "2024-03-01 Buy\n\tAssets:Broker  10 AAPL @ 150 USD\n\tAssets:Cash  -1500 USD"
+        )
+        .is_ok());
+        assert!(balanced(
+            "2024-03-01 Sell\n\tAssets:Broker  -10 AAPL @@ 1600 USD\n\tAssets:Cash  1600 USD"
+        )
+        .is_ok());
+        let t =
+            balanced("2024-03-01 Buy\n\tAssets:Broker  10 AAPL @ $150.25\n\tAssets:Cash").unwrap();
+        assert_eq!(
+            t.postings[1].amount,
+            Some(Amount::new("$", Decimal::new(-150250, 2)))
+        );
+    }
+
+    #[test]
+    fn balance_to_written_precision() {
+        assert!(balanced(
+            "2024-03-01 Buy\n\tAssets:Broker  3 VTI @ 33.3333 USD\n\tAssets:Cash  -100.00 USD"
+        )
+        .is_ok());
+        assert!(matches!(
+            balanced(
+                "2024-03-01 Buy\n\tAssets:Broker  3 VTI @ 33.3333 USD\n\tAssets:Cash  -100.01 USD"
+            ),
+            Err(Imbalance::Unbalanced(_))
+        ));
+        assert!(matches!(
+            balanced("2024-03-01 Shop\n\tExpenses:Food  USD 20.001\n\tAssets:Cash  USD -20.00"),
+            Err(Imbalance::Unbalanced(_))
+        ));
+        let t = balanced("2024-03-01 Buy\n\tAssets:Broker  3 VTI @ 33.3333 USD\n\tAssets:Cash")
+            .unwrap();
+        assert_eq!(
+            t.postings[1].amount,
+            Some(Amount::new("USD", Decimal::new(-999999, 4)))
+        );
+    }
+
+    #[test]
+    fn balance_at_lot_cost() {
+        let t = balanced(
+            "2024-03-01 Buy\n\tAssets:Broker  10 AAPL {150 USD} [2024-03-01]\n\tAssets:Cash",
+        )
+        .unwrap();
+        assert_eq!(
+            t.postings[1].amount,
+            Some(Amount::new("USD", Decimal::new(-1500, 0)))
+        );
+        assert!(balanced("2024-04-01 Sell\n\tAssets:Broker  -10 AAPL {150 USD} @ 160 USD\n\tAssets:Cash  1600 USD").is_ok());
+    }
+
+    #[test]
+    fn balance_virtual_postings() {
+        let t = balanced("2024-03-01 Shop\n\tExpenses:Food  USD 20\n\tAssets:Checking\n\t(Budget:Food)  USD -20\n\t[Envelopes:Food]  USD -20\n\t[Envelopes:Available]").unwrap();
+        assert_eq!(
+            t.postings[1].amount,
+            Some(Amount::new("USD", Decimal::new(-20, 0)))
+        );
+        assert_eq!(
+            t.postings[4].amount,
+            Some(Amount::new("USD", Decimal::new(20, 0)))
+        );
+        assert!(matches!(
+            balanced("2024-03-01 Shop\n\tExpenses:Food  USD 20\n\tAssets:Checking  USD -20\n\t[Envelopes:Food]  USD -20"),
+            Err(Imbalance::Unbalanced(_))
+        ));
+    }
+
+    #[test]
+    fn reject_unbalanced() {
+        let mut remainder = Balance::default();
+        remainder.add(&Amount {
+            currency: "USD",
+            amount: Decimal::new(35, 0),
+            ..Default::default()
+        });
+        assert_eq!(
+            balanced("2024-03-01 Shop\n\tExpenses:Food  USD20\n\tLiabilities:Credit  USD15"),
+            Err(Imbalance::Unbalanced(remainder))
+        );
+    }
+
+    #[test]
+    fn reject_overflowing_cost() {
+        assert_eq!(
+            balanced("2024-03-01 Buy\n\tAssets:Broker  79228162514264337593543950335 AAPL @ 2 USD\n\tAssets:Cash"),
+            Err(Imbalance::Overflow)
+        );
+    }
+
+    #[test]
+    fn reject_overflowing_sums() {
+        assert_eq!(
+            balanced("2024-03-01 Shop\n\tExpenses:Food  USD 79228162514264337593543950335\n\tExpenses:Drink  USD 79228162514264337593543950335\n\tAssets:Cash"),
+            Err(Imbalance::Overflow)
+        );
+        let half = "USD 50000000000000000000000000000";
+        let source = format!("2024-03-01 Deposit\n\tAssets:Cash  {half}\n\tIncome\n\n2024-03-02 Deposit\n\tAssets:Cash  {half}\n\tIncome\n");
+        let mut journal = parse(&source).unwrap();
+        let errors = journal.balance().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec!["line 5: transaction on 2024-03-02 has amounts too large to add up"]
+        );
+    }
+
+    #[test]
+    fn reject_multiple_elided() {
+        assert_eq!(
+            balanced(
+                "2024-03-01 Shop\n\tExpenses:Food  USD20\n\tLiabilities:Credit\n\tAssets:Cash"
+            ),
+            Err(Imbalance::MultipleElided)
+        );
+    }
+
+    #[test]
+    fn reject_ambiguous_elided() {
+        assert!(matches!(
+            balanced("2024-03-01 Shop\n\tExpenses:Food  USD20\n\tExpenses:Wine  EUR5\n\tLiabilities:Credit"),
+            Err(Imbalance::AmbiguousElided(_))
+        ));
+    }
+
+    #[test]
+    fn balance_journal() {
+        let mut journal = parse("2024-03-01 Shop\n\tExpenses:Food  USD20\n\tLiabilities:Credit\n\n2024-03-02 Oops\n\tExpenses:Food  USD20\n\tLiabilities:Credit  USD2\n").unwrap();
+        let errors = journal.balance().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 5);
+        assert_eq!(
+            errors[0].to_string(),
+            "line 5: transaction on 2024-03-02 does not balance, off by USD 22"
+        );
+        assert!(journal.transactions[0].postings[1].amount.is_some());
+    }
+}
@@ -0,0 +1,528 @@
+use crate::{
+    account, amount_with, comment, entry, skip_blank_lines, transaction_state, without_bom, Amount,
+    Cost, Entry, NumberFormat, ParseError, Posting, PostingKind, Transaction,
+};
+use nom::character::complete::space0;
+use nom::combinator::recognize;
+use nom::sequence::terminated;
+use nom::Finish;
+use std::borrow::Cow;
+use std::fmt;
+
+/// A journal that keeps every byte of its source, so it can be edited and
+/// written back with only the edited lines changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree<'a> {
+    pub items: Vec<SyntaxItem<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxItem<'a> {
+    /// Blank lines, comments and directives, kept verbatim.
+    Text(&'a str),
+    Transaction(TransactionSyntax<'a>),
+}
+
+/// A transaction and its text. The text runs from the start of the header
+/// to the end of the last posting or comment, without its line ending.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionSyntax<'a> {
+    /// The transaction as parsed. Edits change the text only.
+    pub transaction: Transaction<'a>,
+    header: &'a str,
+    lines: Vec<Line<'a>>,
+    /// The number format in effect where the transaction is written.
+    format: NumberFormat,
+}
+
+/// A line below a transaction's header, starting with the line ending that
+/// precedes it and its indentation.
+#[derive(Debug, Clone, PartialEq)]
+enum Line<'a> {
+    Comment(&'a str),
+    Posting(PostingSyntax<'a>),
+}
+
+/// The text of a posting line, split around its account and amount.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostingSyntax<'a> {
+    /// The line ending and indentation, the posting's marker and an opening
+    /// bracket.
+    lead: Cow<'a, str>,
+    account: Cow<'a, str>,
+    /// A closing bracket and the space before the amount.
+    gap: Cow<'a, str>,
+    amount: Option<Cow<'a, str>>,
+    /// Lot, cost, assertion and comments, exactly as written.
+    rest: Cow<'a, str>,
+    /// The number format new amounts are written for.
+    format: NumberFormat,
+}
+
+/// The part of `text` before `part`, a slice of it.
+fn before<'a>(text: &'a str, part: &str) -> &'a str {
+    &text[..part.as_ptr() as usize - text.as_ptr() as usize]
+}
+
+/// The line ending and indentation `line` starts with.
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// `posting` with every amount styled to be read back the same under
+/// `format`.
+fn readable<'a>(posting: &Posting<'a>, format: NumberFormat) -> Posting<'a> {
+    let mut posting = posting.clone();
+    let lot_cost = posting.lot.as_mut().and_then(|lot| lot.cost.as_mut());
+    let costs = lot_cost
+        .into_iter()
+        .chain(&mut posting.cost)
+        .map(|cost| match cost {
+            Cost::Unit(amount) | Cost::Total(amount) => amount,
+        });
+    let assertion = posting
+        .assertion
+        .as_mut()
+        .map(|assertion| &mut assertion.amount);
+    for amount in posting.amount.iter_mut().chain(costs).chain(assertion) {
+        amount.style = format.readable(amount.style);
+    }
+    posting
+}
+
+/// Splits `text` into its first line and the following lines, each of which
+/// starts with the line ending before it.
+fn split_lines(text: &str) -> (&str, Vec<&str>) {
+    let starts: Vec<usize> = text
+        .match_indices('\n')
+        .map(|(i, _)| match text[..i].ends_with('\r') {
+            true => i - 1,
+            false => i,
+        })
+        .collect();
+    let header = &text[..starts.first().copied().unwrap_or(text.len())];
+    let ends = starts.iter().skip(1).copied().chain([text.len()]);
+    let lines = starts
+        .iter()
+        .zip(ends)
+        .map(|(&start, end)| &text[start..end]);
+    (header, lines.collect())
+}
+
+impl<'a> PostingSyntax<'a> {
+    fn parse(line: &'a str, posting: &Posting, format: NumberFormat) -> Option<Self> {
+        let (content, _) = terminated(transaction_state, space0)(line.trim_start()).ok()?;
+        let (_, (kind, account)) = account(content).ok()?;
+        let lead = before(line, account.name);
+        let after = &line[lead.len() + account.name.len()..];
+        let close = match kind {
+            PostingKind::Real => 0,
+            PostingKind::Virtual | PostingKind::BalancedVirtual => 1,
+        };
+        let (gap, amount, rest) = match posting.amount {
+            Some(_) => {
+                let start = after[close..].trim_start_matches([' ', '\t']);
+                let (rest, amount) = recognize(amount_with(format))(start).ok()?;
+                (before(after, start), Some(amount), rest)
+            }
+            None => (&after[..close], None, &after[close..]),
+        };
+        Some(PostingSyntax {
+            lead: Cow::Borrowed(lead),
+            account: Cow::Borrowed(account.name),
+            gap: Cow::Borrowed(gap),
+            amount: amount.map(Cow::Borrowed),
+            rest: Cow::Borrowed(rest),
+            format,
+        })
+    }
+
+    /// Lays out `posting` as a new line starting with `indentation`, with
+    /// its amounts written to be read under `format`.
+    fn new(posting: &Posting, indentation: &str, format: NumberFormat) -> Self {
+        let posting = &readable(posting, format);
+        let account = posting.account_text();
+        let close = match posting.kind {
+            PostingKind::Real => 0,
+            PostingKind::Virtual | PostingKind::BalancedVirtual => 1,
+        };
+        let end = account.len() - close;
+        let lead = &account[..end - posting.account.name.len()];
+        let space = if posting.amount.is_some() { "  " } else { "" };
+        PostingSyntax {
+            lead: Cow::Owned(format!("{}{}", indentation, lead)),
+            account: Cow::Owned(posting.account.name.to_string()),
+            gap: Cow::Owned(format!("{}{}", &account[end..], space)),
+            amount: posting
+                .amount
+                .as_ref()
+                .map(|amount| Cow::Owned(amount.to_string())),
+            rest: Cow::Owned(posting.trailer().replace("\n\t", indentation)),
+            format,
+        }
+    }
+
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    /// Renames the posting's account. Where spaces separate the account from
+    /// its amount, as many are added or removed as keep the amount where it
+    /// was, leaving at least two.
+    pub fn set_account(&mut self, name: &str) {
+        let (bracket, space) = self
+            .gap
+            .split_at(self.gap.len() - self.gap.trim_start_matches([')', ']']).len());
+        if self.amount.is_some() && space.chars().all(|c| c == ' ') {
+            let width = space.len() + self.account.chars().count();
+            let spaces = width.saturating_sub(name.chars().count()).max(2);
+            self.gap = Cow::Owned(format!("{}{}", bracket, " ".repeat(spaces)));
+        }
+        self.account = Cow::Owned(name.to_string());
+    }
+
+    /// The amount as it is written, e.g. `USD 1,000.00`.
+    pub fn amount(&self) -> Option<&str> {
+        self.amount.as_deref()
+    }
+
+    /// Replaces the amount, or adds one two spaces after the account.
+    pub fn set_amount(&mut self, amount: &Amount) {
+        if self.amount.is_none() {
+            self.gap = Cow::Owned(format!("{}  ", self.gap));
+        }
+        let amount = Amount {
+            style: self.format.readable(amount.style),
+            ..amount.clone()
+        };
+        self.amount = Some(Cow::Owned(amount.to_string()));
+    }
+}
+
+impl fmt::Display for PostingSyntax<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let amount = self.amount.as_deref().unwrap_or_default();
+        write!(
+            f,
+            "{}{}{}{}{}",
+            self.lead, self.account, self.gap, amount, self.rest
+        )
+    }
+}
+
+impl<'a> TransactionSyntax<'a> {
+    fn parse(text: &'a str, transaction: Transaction<'a>, format: NumberFormat) -> Option<Self> {
+        let (header, texts) = split_lines(text);
+        let mut postings = transaction.postings.iter();
+        let mut lines = Vec::new();
+        for line in texts {
+            let line = match comment(line.trim_start()) {
+                Ok(_) => Line::Comment(line),
+                Err(_) => Line::Posting(PostingSyntax::parse(line, postings.next()?, format)?),
+            };
+            lines.push(line);
+        }
+        Some(TransactionSyntax {
+            transaction,
+            header,
+            lines,
+            format,
+        })
+    }
+
+    pub fn postings(&self) -> impl Iterator<Item = &PostingSyntax<'a>> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Posting(posting) => Some(posting),
+            Line::Comment(_) => None,
+        })
+    }
+
+    pub fn postings_mut(&mut self) -> impl Iterator<Item = &mut PostingSyntax<'a>> {
+        self.lines.iter_mut().filter_map(|line| match line {
+            Line::Posting(posting) => Some(posting),
+            Line::Comment(_) => None,
+        })
+    }
+
+    /// The line ending the transaction is written with, `\n` unless its lines
+    /// end in `\r\n`.
+    fn line_ending(&self) -> &'static str {
+        let first = match self.lines.first() {
+            Some(Line::Comment(text)) => text,
+            Some(Line::Posting(posting)) => posting.lead.as_ref(),
+            None => "",
+        };
+        match first.starts_with("\r\n") {
+            true => "\r\n",
+            false => "\n",
+        }
+    }
+
+    /// Adds `posting` after the last line of the transaction, with the same
+    /// line ending and indentation as that line.
+    pub fn push_posting(&mut self, posting: &Posting) {
+        let indentation = match self.lines.last() {
+            Some(Line::Posting(last)) => indentation(&last.lead).to_string(),
+            Some(Line::Comment(last)) => indentation(last).to_string(),
+            None => "\n\t".to_string(),
+        };
+        let posting = PostingSyntax::new(posting, &indentation, self.format);
+        self.lines.push(Line::Posting(posting));
+    }
+}
+
+impl fmt::Display for TransactionSyntax<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header)?;
+        for line in &self.lines {
+            match line {
+                Line::Comment(text) => write!(f, "{}", text)?,
+                Line::Posting(posting) => write!(f, "{}", posting)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// Parses a journal like [`crate::parse`], keeping its text.
+    pub fn parse(source: &'a str) -> Result<Self, ParseError> {
+        let mut items = Vec::new();
+        let mut format = NumberFormat::default();
+        // Where the text not yet added as an item starts.
+        let mut text = source;
+        let mut input = without_bom(source);
+        loop {
+            let start = skip_blank_lines(input);
+            if start.is_empty() {
+                break;
+            }
+            let (rest, entry) = entry(format)(start)
+                .finish()
+                .map_err(|e| e.locate(source))?;
+            match entry {
+                Entry::Transaction(transaction) => {
+                    let syntax = before(start, rest);
+                    let syntax = TransactionSyntax::parse(syntax, transaction, format)
+                        .expect("a parsed transaction splits into lines");
+                    if !before(text, start).is_empty() {
+                        items.push(SyntaxItem::Text(before(text, start)));
+                    }
+                    items.push(SyntaxItem::Transaction(syntax));
+                    text = rest;
+                }
+                Entry::DecimalMark(mark) => format.decimal_mark = Some(mark),
+                _ => {}
+            }
+            input = rest;
+        }
+        if !text.is_empty() {
+            items.push(SyntaxItem::Text(text));
+        }
+        Ok(SyntaxTree { items })
+    }
+
+    /// The journal with every transaction printed afresh, with ISO dates,
+    /// tab indentation and amounts aligned: to end at `column` if given, as
+    /// with [`Transaction::to_string_aligned`], or else within each
+    /// transaction. Everything between transactions is kept as it is.
+    pub fn formatted(&self, column: Option<usize>) -> String {
+        let mut text = String::new();
+        for item in &self.items {
+            match item {
+                SyntaxItem::Text(verbatim) => text += verbatim,
+                SyntaxItem::Transaction(syntax) => {
+                    let printed = match column {
+                        Some(column) => syntax.transaction.to_string_aligned(column),
+                        None => syntax.transaction.to_string(),
+                    };
+                    text += &printed.replace('\n', syntax.line_ending());
+                }
+            }
+        }
+        text
+    }
+
+    pub fn transactions_mut(&mut self) -> impl Iterator<Item = &mut TransactionSyntax<'a>> {
+        self.items.iter_mut().filter_map(|item| match item {
+            SyntaxItem::Transaction(transaction) => Some(transaction),
+            SyntaxItem::Text(_) => None,
+        })
+    }
+}
+
+impl fmt::Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            match item {
+                SyntaxItem::Text(text) => write!(f, "{}", text)?,
+                SyntaxItem::Transaction(transaction) => write!(f, "{}", transaction)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::posting;
+
+    const JOURNAL: &str = "; Household accounts
+decimal-mark ,
+
+2024-03-01 * Shop   ; :food:
+\t; receipt: 42
+\tExpenses:Food      1.234,50 EUR  ; big shop
+\t(Budget:Food)    -1.234,50 EUR
+\tAssets:Cash   = 0 EUR
+
+comment
+2024-03-02 Not a transaction
+end comment
+2024/3/3 Rent
+\t! Expenses:Rent  EUR 500,00 @ 1,1 USD
+\tAssets:Bank
+";
+
+    #[test]
+    fn keep_every_byte() {
+        let tree = SyntaxTree::parse(JOURNAL).unwrap();
+        assert_eq!(tree.to_string(), JOURNAL);
+        let transactions: Vec<_> = tree
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                SyntaxItem::Transaction(syntax) => Some(syntax),
+                SyntaxItem::Text(_) => None,
+            })
+            .collect();
+        assert_eq!(transactions.len(), 2);
+        let amounts: Vec<_> = transactions[0].postings().map(|p| p.amount()).collect();
+        assert_eq!(
+            amounts,
+            vec![Some("1.234,50 EUR"), Some("-1.234,50 EUR"), None]
+        );
+    }
+
+    #[test]
+    fn format_transactions() {
+        let tree = SyntaxTree::parse(JOURNAL).unwrap();
+        let formatted = tree.formatted(Some(30));
+        assert_eq!(
+            formatted,
+            "; Household accounts
+decimal-mark ,
+
+2024-03-01 * Shop  ; :food:
+\t; receipt: 42
+\tExpenses:Food     1.234,50 EUR  ; big shop
+\t(Budget:Food)    -1.234,50 EUR
+\tAssets:Cash  = 0 EUR
+
+comment
+2024-03-02 Not a transaction
+end comment
+2024-03-03 Rent
+\t! Expenses:Rent     EUR 500,00 @ 1,1 USD
+\tAssets:Bank
+"
+        );
+        assert_eq!(
+            SyntaxTree::parse(&formatted).unwrap().formatted(Some(30)),
+            formatted
+        );
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        let source = "2024-03-01    Shop   |   Lunch\n\tExpenses:Food  USD 5\n\tAssets:Cash\n\n2024-03-02 *   Rent\n\tExpenses:Rent  USD 500\n\tAssets:Cash\n";
+        let once = SyntaxTree::parse(source).unwrap().formatted(None);
+        assert!(once.starts_with("2024-03-01 Shop | Lunch\n"));
+        assert!(once.contains("\n2024-03-02 * Rent\n"));
+        assert_eq!(SyntaxTree::parse(&once).unwrap().formatted(None), once);
+    }
+
+    #[test]
+    fn indent_with_tabs() {
+        let source = "2024-03-01 Shop\n    Expenses:Food  USD 5\n  ; lunch\n \t Assets:Cash\n";
+        let formatted = SyntaxTree::parse(source).unwrap().formatted(None);
+        assert_eq!(
+            formatted,
+            "2024-03-01 Shop\n\tExpenses:Food  USD 5\n\t; lunch\n\tAssets:Cash\n"
+        );
+    }
+
+    #[test]
+    fn keep_crlf_and_bom() {
+        let source = include_str!("../tests/fixtures/crlf/bank.ledger");
+        let tree = SyntaxTree::parse(source).unwrap();
+        assert_eq!(tree.to_string(), source);
+        let formatted = tree.formatted(None);
+        assert!(formatted.starts_with('\u{feff}'));
+        assert_eq!(
+            formatted.matches('\n').count(),
+            source.matches('\n').count()
+        );
+        assert_eq!(
+            formatted.matches("\r\n").count(),
+            source.matches('\n').count()
+        );
+    }
+
+    #[test]
+    fn edit_postings() {
+        let mut tree = SyntaxTree::parse(JOURNAL).unwrap();
+        {
+            let mut transactions = tree.transactions_mut();
+            let shop = transactions.next().unwrap();
+            let mut postings = shop.postings_mut();
+            postings.next().unwrap().set_account("Expenses:Groceries");
+            postings.next().unwrap().set_account("Budget:Groceries");
+            let cash = postings.next().unwrap();
+            cash.set_account("Assets:Wallet");
+            cash.set_amount(&crate::amount("-1234.5 EUR").unwrap().1);
+            let rent = transactions.next().unwrap();
+            rent.push_posting(&posting("(Budget:Rent)  EUR -500.5  ; :monthly:").unwrap().1);
+            rent.push_posting(&posting("* [Savings]").unwrap().1);
+        }
+        assert_eq!(
+            tree.to_string(),
+            "; Household accounts
+decimal-mark ,
+
+2024-03-01 * Shop   ; :food:
+\t; receipt: 42
+\tExpenses:Groceries  1.234,50 EUR  ; big shop
+\t(Budget:Groceries)  -1.234,50 EUR
+\tAssets:Wallet  -1234,5 EUR   = 0 EUR
+
+comment
+2024-03-02 Not a transaction
+end comment
+2024/3/3 Rent
+\t! Expenses:Rent  EUR 500,00 @ 1,1 USD
+\tAssets:Bank
+\t(Budget:Rent)  EUR -500,5  ; :monthly:
+\t* [Savings]
+"
+        );
+        let text = tree.to_string();
+        let edited = crate::parse(&text).unwrap();
+        let amounts: Vec<_> = edited
+            .transactions
+            .iter()
+            .flat_map(|t| &t.postings)
+            .filter_map(|p| p.amount.as_ref())
+            .map(|a| a.amount)
+            .collect();
+        assert_eq!(
+            amounts[2..],
+            [
+                rust_decimal::Decimal::new(-12345, 1),
+                rust_decimal::Decimal::new(500, 0),
+                rust_decimal::Decimal::new(-5005, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use nom::error::{ContextError, ErrorKind, FromExternalError};
+use std::fmt;
+use std::path::PathBuf;
+
+pub type IResult<'a, O> = nom::IResult<&'a str, O, Error<'a>>;
+
+/// Why a parser rejected its input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    /// A construct such as an amount or a posting was expected but not found.
+    Expected(&'static str),
+    /// The date is well-formed but does not exist, e.g. `2024-02-30`.
+    InvalidDate,
+    /// The number is well-formed but cannot be represented exactly.
+    InvalidNumber,
+    /// An `include` directive in input that was not loaded from a file, so
+    /// there is nothing to resolve its path against.
+    UnresolvedInclude,
+    Nom(ErrorKind),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Expected(what) => write!(f, "expected {}", what),
+            Reason::InvalidDate => write!(f, "invalid date"),
+            Reason::InvalidNumber => write!(f, "invalid number"),
+            Reason::UnresolvedInclude => write!(f, "include outside of a file, use Sources"),
+            Reason::Nom(kind) => write!(f, "unexpected input ({})", kind.description()),
+        }
+    }
+}
+
+/// The error type of every parser in this crate: the input that was left
+/// when parsing failed, and why it failed. Use [`Error::locate`] to turn it
+/// into a [`ParseError`] that can be shown to a user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error<'a> {
+    pub input: &'a str,
+    pub reason: Reason,
+}
+
+impl<'a> Error<'a> {
+    pub fn new(input: &'a str, reason: Reason) -> Self {
+        Error { input, reason }
+    }
+
+    /// Resolves the failure position against the complete `source` the parser
+    /// was run on.
+    pub fn locate(&self, source: &str) -> ParseError {
+        let offset = source.len().saturating_sub(self.input.len());
+        let before = &source[..offset];
+        let line_start = match before.rfind('\n') {
+            Some(i) => i + 1,
+            // A byte order mark is not part of the first line.
+            None if before.starts_with('\u{feff}') => '\u{feff}'.len_utf8(),
+            None => 0,
+        };
+        let line_end = source[offset..]
+            .find(['\r', '\n'])
+            .map_or(source.len(), |i| offset + i);
+        ParseError {
+            file: None,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            snippet: source[line_start..line_end].to_string(),
+            reason: self.reason.clone(),
+        }
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a str> for Error<'a> {
+    fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
+        Error::new(input, Reason::Nom(kind))
+    }
+
+    fn append(_: &'a str, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a> ContextError<&'a str> for Error<'a> {
+    fn add_context(input: &'a str, ctx: &'static str, other: Self) -> Self {
+        // Keep specific reasons such as an invalid date; only replace nom's
+        // generic error kinds with the construct we were trying to parse.
+        match other.reason {
+            Reason::Nom(_) => Error::new(input, Reason::Expected(ctx)),
+            _ => other,
+        }
+    }
+}
+
+impl<'a, E> FromExternalError<&'a str, E> for Error<'a> {
+    fn from_external_error(input: &'a str, kind: ErrorKind, _: E) -> Self {
+        Error::new(input, Reason::Nom(kind))
+    }
+}
+
+/// A parse failure with enough context to point a user at the problem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub file: Option<PathBuf>,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    /// The full line the error occurred on.
+    pub snippet: String,
+    pub reason: Reason,
+}
+
+impl ParseError {
+    pub fn with_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:", file.display())?,
+            None => write!(f, "<input>:")?,
+        }
+        writeln!(f, "{}:{}: {}", self.line, self.column, self.reason)?;
+        writeln!(f, "  {}", self.snippet)?;
+        // Tabs in the snippet are copied, so the caret lines up with it
+        // however wide they are shown.
+        let mut indent: String = self
+            .snippet
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let padding = (self.column - 1).saturating_sub(indent.chars().count());
+        indent.extend(std::iter::repeat_n(' ', padding));
+        write!(f, "  {}^", indent)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locate_error() {
+        let source = "first line\nsecond line\nthird";
+        let err = Error::new(&source[18..], Reason::Expected("amount"));
+        let located = err.locate(source);
+        assert_eq!(located.line, 2);
+        assert_eq!(located.column, 8);
+        assert_eq!(located.snippet, "second line");
+        assert_eq!(
+            located.with_file("journal.ledger").to_string(),
+            "journal.ledger:2:8: expected amount\n  second line\n         ^"
+        );
+    }
+
+    #[test]
+    fn caret_under_tabs() {
+        let source = "2024-03-01 Shop\n\tExpenses:Food  twenty\n";
+        let err = Error::new(&source[32..], Reason::Expected("amount"));
+        assert_eq!(
+            err.locate(source).to_string(),
+            "<input>:2:17: expected amount\n  \tExpenses:Food  twenty\n  \t               ^"
+        );
+    }
+
+    #[test]
+    fn locate_error_at_end_of_input() {
+        let source = "line\n";
+        let located = Error::new("", Reason::Expected("date")).locate(source);
+        assert_eq!(located.line, 2);
+        assert_eq!(located.column, 1);
+        assert_eq!(located.snippet, "");
+    }
+}
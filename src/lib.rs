@@ -1,29 +1,60 @@
 use chrono::NaiveDate;
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_till, take_until},
+    bytes::complete::{is_not, tag, take_till, take_until},
     character::complete::{
-        alpha1, char, digit1, line_ending, newline, not_line_ending, space0, tab,
+        char, digit1, line_ending, none_of, not_line_ending, one_of, space0, space1,
     },
-    combinator::{map, map_res, opt, value},
-    multi::separated_list0,
-    sequence::{delimited, preceded, separated_pair, tuple},
-    IResult,
+    combinator::{cut, eof, map, map_res, not, opt, peek, recognize, rest, value, verify},
+    error::context,
+    multi::{many0, many1, separated_list1},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    Finish,
 };
 use rust_decimal::Decimal;
-use std::str::FromStr;
-use util::{float, space2};
+use std::ops::Neg;
+use std::path::Path;
+use util::{number, space2};
 
+pub use assertion::AssertionError;
+pub use balance::{Balance, BalanceError, Imbalance, Overflow};
+pub use cst::{PostingSyntax, SyntaxItem, SyntaxTree, TransactionSyntax};
+pub use error::{Error, IResult, ParseError, Reason};
+pub use metadata::Metadata;
+pub use price::PriceDb;
+pub use query::Query;
+pub use report::{
+    BalanceOptions, BalanceReport, BalanceRow, RegisterOptions, RegisterReport, RegisterRow,
+};
+pub use source::{LoadError, Sources};
+pub use strict::StrictError;
+pub use style::{AmountStyle, NumberFormat, Side};
+pub use valuation::{Valuation, Valuer};
+
+mod assertion;
+mod balance;
+mod cst;
+mod error;
+mod metadata;
+mod price;
+mod print;
+mod query;
+mod report;
+mod source;
+mod strict;
+mod style;
 mod util;
-#[derive(Debug, Clone, PartialEq)]
+mod valuation;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TransactionState {
     Cleared,
+    #[default]
     Uncleared,
     Pending,
 }
 
-pub fn transaction_state(input: &str) -> IResult<&str, TransactionState> {
+pub fn transaction_state(input: &str) -> IResult<'_, TransactionState> {
     let (input, state) = opt(alt((
         value(TransactionState::Cleared, char('*')),
         value(TransactionState::Pending, char('!')),
@@ -31,43 +62,390 @@ pub fn transaction_state(input: &str) -> IResult<&str, TransactionState> {
     Ok((input, state.unwrap_or(TransactionState::Uncleared)))
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Account<'a> {
-    name: &'a str,
+    pub name: &'a str,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default)]
 pub struct Amount<'a> {
-    currency: &'a str,
-    amount: Decimal,
+    pub currency: &'a str,
+    pub amount: Decimal,
+    /// How the amount was written. It does not take part in comparisons:
+    /// `USD 1,000` and `1000.00 USD` are equal.
+    pub style: AmountStyle,
 }
 
-fn amount(input: &str) -> IResult<&str, Amount> {
-    let (input, (currency, amount)) = alt((
-        separated_pair(alpha1, space0, float),
-        separated_pair(alpha1, space0, digit1),
-        map(separated_pair(float, space0, alpha1), |(a, c)| (c, a)),
-        map(separated_pair(digit1, space0, alpha1), |(a, c)| (c, a)),
-    ))(input)?;
-    let amount = Amount {
-        currency,
-        amount: Decimal::from_str(amount).unwrap(),
-    };
-    Ok((input, amount))
+impl<'a> Amount<'a> {
+    pub fn new(currency: &'a str, amount: Decimal) -> Self {
+        Amount {
+            currency,
+            amount,
+            style: AmountStyle::default(),
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl PartialEq for Amount<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.currency == other.currency && self.amount == other.amount
+    }
+}
+
+impl<'a> Neg for Amount<'a> {
+    type Output = Amount<'a>;
+
+    fn neg(self) -> Self::Output {
+        Amount {
+            amount: -self.amount,
+            ..self
+        }
+    }
+}
+
+fn sign(input: &str) -> IResult<'_, Option<char>> {
+    opt(one_of("+-"))(input)
+}
+
+/// A commodity symbol such as `USD`, `$`, `€` or `BRK.B`, or any name in
+/// double quotes, e.g. `"VANGUARD 500"`, which is returned without the quotes.
+pub fn commodity(input: &str) -> IResult<'_, &str> {
+    alt((
+        delimited(char('"'), is_not("\"\r\n"), char('"')),
+        recognize(separated_list1(
+            char('.'),
+            is_not("0123456789 \t\r\n\"-+.,;@{}[]()=*"),
+        )),
+    ))(input)
+}
+
+/// Parses an amount such as `USD -1,234.56`, `-$20.00` or `1.234,56 EUR`,
+/// reading ambiguous numbers according to `format`.
+pub fn amount_with<'a>(format: NumberFormat) -> impl FnMut(&'a str) -> IResult<'a, Amount<'a>> {
+    move |input| {
+        let (rest, (sign, (currency, side, space, number_sign, number))) = context(
+            "amount",
+            pair(
+                sign,
+                alt((
+                    map(
+                        tuple((commodity, space0, sign, number)),
+                        |(currency, space, sign, number)| {
+                            (currency, Side::Left, space, sign, number)
+                        },
+                    ),
+                    map(
+                        tuple((number, space0, commodity)),
+                        |(number, space, currency)| (currency, Side::Right, space, None, number),
+                    ),
+                )),
+            ),
+        )(input)?;
+        let invalid = || nom::Err::Failure(Error::new(input, Reason::InvalidNumber));
+        // A sign goes either before the commodity or before the number.
+        let sign = match (sign, number_sign) {
+            (Some(_), Some(_)) => return Err(invalid()),
+            (sign, number_sign) => sign.or(number_sign),
+        };
+        let (mut amount, style) = format.read(number).ok_or_else(invalid)?;
+        if sign == Some('-') {
+            amount.set_sign_negative(true);
+        }
+        Ok((
+            rest,
+            Amount {
+                currency,
+                amount,
+                style: AmountStyle {
+                    commodity_side: side,
+                    commodity_spaced: !space.is_empty(),
+                    ..style
+                },
+            },
+        ))
+    }
+}
+
+pub fn amount(input: &str) -> IResult<'_, Amount<'_>> {
+    amount_with(NumberFormat::default())(input)
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Posting<'a> {
-    account: Account<'a>,
-    amount: Option<Amount<'a>>,
+    /// The posting's own `*` or `!` marker. See [`Posting::effective_state`].
+    pub state: TransactionState,
+    pub account: Account<'a>,
+    pub kind: PostingKind,
+    pub amount: Option<Amount<'a>>,
+    pub lot: Option<Lot<'a>>,
+    pub cost: Option<Cost<'a>>,
+    /// A balance assertion such as `= USD 100`. Without an amount, the
+    /// posting is a balance assignment: its amount is whatever brings the
+    /// account to the asserted balance.
+    pub assertion: Option<BalanceAssertion<'a>>,
+    /// The text of the posting's trailing comment and of any comment lines
+    /// directly below it, without the leading `;`.
+    pub comments: Vec<&'a str>,
+    /// Whether the first of `comments` ends the posting's line, rather than
+    /// being on a line of its own below it.
+    pub trailing_comment: bool,
+    /// Tags and values from the posting's comments, plus those of its
+    /// transaction.
+    pub metadata: Metadata<'a>,
+    /// A date overriding the transaction's, given in a comment as
+    /// `[2024-03-05]` or `date: 2024-03-05`.
+    pub date: Option<NaiveDate>,
+    /// An auxillary date overriding the transaction's, given in a comment as
+    /// `[=2024-03-05]` or `date2: 2024-03-05`.
+    pub auxillary_date: Option<NaiveDate>,
+    /// 1-based line of the posting. For a transaction parsed on its own, this
+    /// is the number of lines after the transaction's first line instead.
+    pub line: usize,
+}
+
+impl<'a> Posting<'a> {
+    /// The posting's date: its own if it has one, else its transaction's.
+    pub fn effective_date(&self, transaction: &Transaction) -> NaiveDate {
+        self.date.unwrap_or(transaction.date)
+    }
+
+    pub fn effective_auxillary_date(&self, transaction: &Transaction) -> Option<NaiveDate> {
+        self.auxillary_date.or(transaction.auxillary_date)
+    }
+
+    /// The posting's status: its own marker if it has one, else its
+    /// transaction's.
+    pub fn effective_state(&self, transaction: &Transaction) -> TransactionState {
+        match self.state {
+            TransactionState::Uncleared => transaction.state,
+            state => state,
+        }
+    }
+}
+
+/// The price paid for a posting's amount, written after it with `@` or `@@`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cost<'a> {
+    /// `@ 150 USD`: the price of a single unit.
+    Unit(Amount<'a>),
+    /// `@@ 1500 USD`: the price of the whole amount.
+    Total(Amount<'a>),
+}
+
+impl<'a> Cost<'a> {
+    /// What `quantity` cost in total, negative if `quantity` is, or `None`
+    /// if that is too large to represent.
+    pub fn total(&self, quantity: &Amount) -> Option<Amount<'a>> {
+        match self {
+            Cost::Unit(price) => Some(Amount {
+                amount: price.amount.checked_mul(quantity.amount)?,
+                ..price.clone()
+            }),
+            Cost::Total(price) => {
+                let mut amount = price.amount.abs();
+                amount.set_sign_negative(quantity.amount.is_sign_negative());
+                Some(Amount {
+                    amount,
+                    ..price.clone()
+                })
+            }
+        }
+    }
+}
+
+fn cost_with<'a>(format: NumberFormat) -> impl FnMut(&'a str) -> IResult<'a, Cost<'a>> {
+    preceded(
+        space0,
+        alt((
+            map(
+                preceded(tag("@@"), cut(preceded(space0, amount_with(format)))),
+                Cost::Total,
+            ),
+            map(
+                preceded(char('@'), cut(preceded(space0, amount_with(format)))),
+                Cost::Unit,
+            ),
+        )),
+    )
+}
+
+/// Identifies the lot a commodity amount belongs to, from annotations such as
+/// `10 AAPL {150 USD} [2024-01-03] (lot-a)`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Lot<'a> {
+    /// What the lot was acquired for: per unit with `{150 USD}`, or in total
+    /// with `{{1500 USD}}`.
+    pub cost: Option<Cost<'a>>,
+    /// `[2024-01-03]`: when the lot was acquired.
+    pub date: Option<NaiveDate>,
+    /// `(lot-a)`: a free-form label.
+    pub note: Option<&'a str>,
+}
+
+enum LotAnnotation<'a> {
+    Cost(Cost<'a>),
+    Date(NaiveDate),
+    Note(&'a str),
+}
+
+fn lot_annotation<'a>(
+    format: NumberFormat,
+) -> impl FnMut(&'a str) -> IResult<'a, LotAnnotation<'a>> {
+    let amount = move |input| delimited(space0, amount_with(format), space0)(input);
+    alt((
+        map(delimited(tag("{{"), cut(amount), cut(tag("}}"))), |total| {
+            LotAnnotation::Cost(Cost::Total(total))
+        }),
+        map(delimited(char('{'), cut(amount), cut(char('}'))), |unit| {
+            LotAnnotation::Cost(Cost::Unit(unit))
+        }),
+        map(
+            delimited(char('['), cut(date), cut(char(']'))),
+            LotAnnotation::Date,
+        ),
+        map(
+            delimited(char('('), is_not(")\r\n"), cut(char(')'))),
+            LotAnnotation::Note,
+        ),
+    ))
+}
+
+/// Lot annotations following an amount, in any order.
+fn lot_with<'a>(format: NumberFormat) -> impl FnMut(&'a str) -> IResult<'a, Option<Lot<'a>>> {
+    map(
+        many0(preceded(space0, lot_annotation(format))),
+        |annotations| {
+            if annotations.is_empty() {
+                return None;
+            }
+            let mut lot = Lot::default();
+            for annotation in annotations {
+                match annotation {
+                    LotAnnotation::Cost(cost) => lot.cost = Some(cost),
+                    LotAnnotation::Date(date) => lot.date = Some(date),
+                    LotAnnotation::Note(note) => lot.note = Some(note),
+                }
+            }
+            Some(lot)
+        },
+    )
+}
+
+/// Asserts the balance of a posting's account after the posting, e.g.
+/// `Assets:Checking  USD 0 = USD 1523.10`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceAssertion<'a> {
+    pub amount: Amount<'a>,
+    /// `==`: the account must hold nothing besides the asserted commodity.
+    pub total: bool,
+    /// `=*`: the balance includes all subaccounts.
+    pub inclusive: bool,
+}
+
+fn assertion_with<'a>(
+    format: NumberFormat,
+) -> impl FnMut(&'a str) -> IResult<'a, BalanceAssertion<'a>> {
+    map(
+        tuple((
+            preceded(space0, char('=')),
+            map(opt(char('=')), |total| total.is_some()),
+            map(opt(char('*')), |inclusive| inclusive.is_some()),
+            cut(preceded(space0, amount_with(format))),
+        )),
+        |(_, total, inclusive, amount)| BalanceAssertion {
+            amount,
+            total,
+            inclusive,
+        },
+    )
+}
+
+/// Whether a posting is real or virtual, i.e. written as `(Account)` or
+/// `[Account]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PostingKind {
+    #[default]
+    Real,
+    /// `(Account)`: does not need to balance.
+    Virtual,
+    /// `[Account]`: must balance with the other bracketed postings.
+    BalancedVirtual,
 }
 
-fn posting(input: &str) -> IResult<&str, Posting> {
-    let (input, account) = map(alt((take_until("  "), take_till(|c| c == '\n'))), |name| {
-        Account { name }
-    })(input)?;
-    let (input, amount) = opt(preceded(space2, amount))(input)?;
-    Ok((input, Posting { account, amount }))
+/// An account name ending at any of `delimiters`. Names may contain single
+/// spaces, so two spaces in a row end them too, as does ` ;` starting a
+/// comment.
+fn account_name<'a>(delimiters: &'static str) -> impl FnMut(&'a str) -> IResult<'a, &'a str> {
+    recognize(many1(alt((
+        is_not(delimiters),
+        terminated(tag(" "), peek(pair(not(char(';')), none_of(delimiters)))),
+    ))))
+}
+
+fn account(input: &str) -> IResult<'_, (PostingKind, Account<'_>)> {
+    let (input, (kind, name)) = alt((
+        map(
+            delimited(char('('), account_name(" \t\r\n)"), char(')')),
+            |name| (PostingKind::Virtual, name),
+        ),
+        map(
+            delimited(char('['), account_name(" \t\r\n]"), char(']')),
+            |name| (PostingKind::BalancedVirtual, name),
+        ),
+        map(account_name(" \t\r\n"), |name| (PostingKind::Real, name)),
+    ))(input)?;
+    Ok((input, (kind, Account { name })))
+}
+
+fn end_of_line(input: &str) -> IResult<'_, ()> {
+    context(
+        "end of line",
+        value((), preceded(space0, peek(alt((line_ending, eof))))),
+    )(input)
+}
+
+/// A `;` comment running to the end of the line.
+pub fn comment(input: &str) -> IResult<'_, &str> {
+    map(preceded(char(';'), not_line_ending), str::trim)(input)
+}
+
+fn posting_with<'a>(format: NumberFormat) -> impl FnMut(&'a str) -> IResult<'a, Posting<'a>> {
+    move |input| {
+        let (input, state) = terminated(transaction_state, space0)(input)?;
+        let (input, (kind, account)) = context("account", account)(input)?;
+        let (input, amount) = opt(preceded(
+            pair(space2, not(alt((end_of_line, value((), one_of(";=")))))),
+            cut(amount_with(format)),
+        ))(input)?;
+        let (input, (lot, cost)) = match amount {
+            Some(_) => pair(lot_with(format), opt(cost_with(format)))(input)?,
+            None => (input, (None, None)),
+        };
+        let (input, assertion) = opt(assertion_with(format))(input)?;
+        let (input, comment) = opt(preceded(space0, comment))(input)?;
+        Ok((
+            input,
+            Posting {
+                state,
+                account,
+                kind,
+                amount,
+                lot,
+                cost,
+                assertion,
+                trailing_comment: comment.is_some(),
+                comments: comment.into_iter().collect(),
+                metadata: Metadata::default(),
+                date: None,
+                auxillary_date: None,
+                line: 0,
+            },
+        ))
+    }
+}
+
+pub fn posting(input: &str) -> IResult<'_, Posting<'_>> {
+    posting_with(NumberFormat::default())(input)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -79,72 +457,675 @@ pub struct Transaction<'a> {
     pub merchant: Option<&'a str>,
     pub memo: &'a str,
     pub postings: Vec<Posting<'a>>,
+    /// The text of the header's trailing comment and of any comment lines
+    /// before the first posting, without the leading `;`.
+    pub comments: Vec<&'a str>,
+    /// Whether the first of `comments` ends the header line, rather than
+    /// being on a line of its own below it.
+    pub trailing_comment: bool,
+    /// Tags and values from the transaction's comments.
+    pub metadata: Metadata<'a>,
+    /// Whether the transaction's lines are indented with both tabs and
+    /// spaces. See [`Journal::check_strict`].
+    pub mixed_indentation: bool,
+    /// The file the transaction was read from, when it was loaded through
+    /// [`Sources`].
+    pub file: Option<&'a Path>,
+    /// 1-based line the transaction starts on, or 0 when it was parsed on its
+    /// own rather than as part of a journal.
+    pub line: usize,
 }
 
-pub fn date(input: &str) -> IResult<&str, NaiveDate> {
-    let (input, (year, _, month, _, day)) = tuple((
-        map_res(digit1, str::parse),
-        alt((tag("-"), tag("/"))),
-        map_res(digit1, str::parse),
-        alt((tag("-"), tag("/"))),
-        map_res(digit1, str::parse),
-    ))(input)?;
-    Ok((
-        input,
-        NaiveDate::from_ymd_opt(year, month, day).expect("Invalid date"),
-    ))
+pub fn date(input: &str) -> IResult<'_, NaiveDate> {
+    let (rest, (year, _, month, _, day)) = context(
+        "date",
+        tuple((
+            map_res(digit1, str::parse),
+            alt((tag("-"), tag("/"))),
+            map_res(digit1, str::parse),
+            alt((tag("-"), tag("/"))),
+            map_res(digit1, str::parse),
+        )),
+    )(input)?;
+    let date = NaiveDate::from_ymd_opt(year, month, day)
+        .ok_or_else(|| nom::Err::Failure(Error::new(input, Reason::InvalidDate)))?;
+    Ok((rest, date))
 }
 
-pub fn description(input: &str) -> IResult<&str, (Option<&str>, &str)> {
-    let (input, merchant) = opt(take_until(" | "))(input)?;
-    let (input, memo) = if merchant.is_some() {
-        preceded(tag(" | "), not_line_ending)(input)?
-    } else {
-        not_line_ending(input)?
+pub fn description(input: &str) -> IResult<'_, (Option<&str>, &str)> {
+    let (input, text) = take_till(|c| c == ';' || c == '\r' || c == '\n')(input)?;
+    let text = text.trim();
+    let description = match text.split_once(" | ") {
+        Some((merchant, memo)) => (Some(merchant.trim_end()), memo.trim_start()),
+        None => (None, text),
     };
-    Ok((input, (merchant, memo)))
+    Ok((input, description))
 }
 
-pub fn auxillary_date(input: &str) -> IResult<&str, NaiveDate> {
+pub fn auxillary_date(input: &str) -> IResult<'_, NaiveDate> {
     preceded(tag("="), date)(input)
 }
 
-pub fn code(input: &str) -> IResult<&str, &str> {
+/// Posting dates written in a comment as `[DATE]`, `[=AUX]` or
+/// `[DATE=AUX]`.
+fn bracketed_dates(input: &str) -> IResult<'_, (Option<NaiveDate>, Option<NaiveDate>)> {
+    delimited(char('['), pair(opt(date), opt(auxillary_date)), char(']'))(input)
+}
+
+/// Reads a posting's date overrides from its own comments and metadata.
+/// `source` is the input the transaction was parsed from, so an invalid date
+/// can be reported where it was written.
+fn posting_dates<'a>(
+    posting: &mut Posting<'a>,
+    metadata: &Metadata<'a>,
+    source: &'a str,
+) -> Result<(), nom::Err<Error<'a>>> {
+    // Comments and metadata values are slices of `source`, but do not run to
+    // its end, so errors in them are reported from their position in it.
+    let invalid_date = |text: &str| {
+        let offset = text.as_ptr() as usize - source.as_ptr() as usize;
+        nom::Err::Failure(Error::new(&source[offset..], Reason::InvalidDate))
+    };
+    for comment in &posting.comments {
+        for (i, _) in comment.match_indices('[') {
+            // Brackets that hold no dates are just text, but a date that does
+            // not exist is an error.
+            let dates = match bracketed_dates(&comment[i..]) {
+                Ok((_, dates)) => dates,
+                Err(nom::Err::Failure(e)) => return Err(invalid_date(e.input)),
+                Err(_) => continue,
+            };
+            posting.date = dates.0.or(posting.date);
+            posting.auxillary_date = dates.1.or(posting.auxillary_date);
+        }
+    }
+    let tagged = |key| match metadata.get(key) {
+        Some(value) => match terminated(date, eof)(value) {
+            Ok((_, date)) => Ok(Some(date)),
+            Err(_) => Err(invalid_date(value)),
+        },
+        None => Ok(None),
+    };
+    if let Some(date) = tagged("date")? {
+        posting.date = Some(date);
+    }
+    if let Some(date) = tagged("date2")? {
+        posting.auxillary_date = Some(date);
+    }
+    Ok(())
+}
+
+pub fn code(input: &str) -> IResult<'_, &str> {
     delimited(tag("("), take_until(")"), tag(")"))(input)
 }
 
-pub fn transaction(input: &str) -> IResult<&str, Transaction> {
-    let (input, date) = date(input)?;
-    let (input, auxillary_date) = opt(auxillary_date)(input)?;
-    let (input, _) = char(' ')(input)?;
-    let (input, state) = transaction_state(input)?;
-    let (input, _) = opt(char(' '))(input)?;
-    let (input, code) = opt(code)(input)?;
-    let (input, _) = opt(char(' '))(input)?;
-    let (input, (merchant, memo)) = description(input)?;
-    let (input, postings) = preceded(
-        newline,
-        separated_list0(line_ending, preceded(tab, posting)),
+/// Moves to the start of the next line's content, if it is indented by
+/// spaces or tabs and not blank, returning the indentation.
+fn indented_line(input: &str) -> IResult<'_, &str> {
+    preceded(
+        line_ending,
+        terminated(space1, not(alt((value((), line_ending), value((), eof))))),
+    )(input)
+}
+
+/// Whether `indentations` mix tabs and spaces, within a line or between
+/// lines.
+fn mixed_indentation(indentations: &[&str]) -> bool {
+    let all = indentations.concat();
+    all.contains(' ') && all.contains('\t')
+}
+
+/// Parses a transaction, reading ambiguous amounts according to `format`.
+pub fn transaction_with<'a>(
+    format: NumberFormat,
+) -> impl FnMut(&'a str) -> IResult<'a, Transaction<'a>> {
+    move |input| {
+        let start = input;
+        let (input, date) = date(input)?;
+        let (input, auxillary_date) = opt(auxillary_date)(input)?;
+        // Nothing needs to follow the date.
+        let (input, _) = context("space", alt((value((), char(' ')), end_of_line)))(input)?;
+        let (input, state) = transaction_state(input)?;
+        let (input, _) = opt(char(' '))(input)?;
+        let (input, code) = opt(code)(input)?;
+        let (input, _) = opt(char(' '))(input)?;
+        let (input, (merchant, memo)) = description(input)?;
+        let (mut input, header_comment) = opt(comment)(input)?;
+        let trailing_comment = header_comment.is_some();
+        let mut comments: Vec<_> = header_comment.into_iter().collect();
+        let mut postings: Vec<Posting> = Vec::new();
+        let mut indentations = Vec::new();
+        while let Ok((line, indentation)) = indented_line(input) {
+            indentations.push(indentation);
+            // Indented comment lines belong to the posting above them, or to the
+            // transaction itself if there is none yet.
+            if let Ok((rest, comment)) = comment(line) {
+                match postings.last_mut() {
+                    Some(posting) => posting.comments.push(comment),
+                    None => comments.push(comment),
+                }
+                input = rest;
+                continue;
+            }
+            // Once a line is indented it has to be a posting; anything after the
+            // amount other than whitespace is an error rather than the next entry.
+            let (rest, mut posting) = cut(terminated(posting_with(format), end_of_line))(line)?;
+            posting.line = lines_between(start, line);
+            postings.push(posting);
+            input = rest;
+        }
+        let metadata = Metadata::from_comments(&comments);
+        for posting in &mut postings {
+            let own = Metadata::from_comments(&posting.comments);
+            posting_dates(posting, &own, start)?;
+            posting.metadata = own;
+            posting.metadata.inherit(&metadata);
+        }
+        Ok((
+            input,
+            Transaction {
+                date,
+                auxillary_date,
+                state,
+                code,
+                merchant,
+                memo,
+                postings,
+                comments,
+                trailing_comment,
+                metadata,
+                mixed_indentation: mixed_indentation(&indentations),
+                file: None,
+                line: 0,
+            },
+        ))
+    }
+}
+
+pub fn transaction(input: &str) -> IResult<'_, Transaction<'_>> {
+    transaction_with(NumberFormat::default())(input)
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Journal<'a> {
+    pub transactions: Vec<Transaction<'a>>,
+    pub comments: Vec<Comment<'a>>,
+    /// Accounts declared with `account` directives, in file order.
+    pub accounts: Vec<AccountDeclaration<'a>>,
+    /// Commodities declared with `commodity` directives, in file order.
+    pub commodities: Vec<CommodityDeclaration<'a>>,
+    /// Market prices from `P` directives, in file order.
+    pub prices: Vec<Price<'a>>,
+}
+
+/// A comment outside of any transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment<'a> {
+    pub file: Option<&'a Path>,
+    /// 1-based line the comment starts on.
+    pub line: usize,
+    /// The comment exactly as written, including its `;`, `#`, `%`, `|` or `*`
+    /// marker, or the `comment` and `end comment` lines of a block.
+    pub text: &'a str,
+}
+
+/// An `account` directive and its indented subdirectives:
+///
+/// ```text
+/// account Expenses:Food
+///     note Groceries and eating out
+///     alias food
+///     type Expense
+///     assert commodity == "USD"
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AccountDeclaration<'a> {
+    pub name: &'a str,
+    pub note: Option<&'a str>,
+    /// Other names postings may use for the account. They are replaced by
+    /// the declared name in every transaction after the declaration.
+    pub aliases: Vec<&'a str>,
+    pub account_type: Option<&'a str>,
+    /// The expressions of `assert` subdirectives, kept as written.
+    pub asserts: Vec<&'a str>,
+    /// The expressions of `check` subdirectives, kept as written.
+    pub checks: Vec<&'a str>,
+    pub file: Option<&'a Path>,
+    /// 1-based line of the `account` line.
+    pub line: usize,
+}
+
+/// A `commodity` directive and its indented subdirectives:
+///
+/// ```text
+/// commodity USD
+///     note US dollars
+///     format USD 1,000.00
+///     nomarket
+///     default
+/// ```
+///
+/// The format may also be given on the directive line itself, as in
+/// `commodity USD 1,000.00`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CommodityDeclaration<'a> {
+    pub symbol: &'a str,
+    pub note: Option<&'a str>,
+    /// How amounts in the commodity should be displayed, taken from the
+    /// example amount of a `format` subdirective.
+    pub format: Option<AmountStyle>,
+    /// Whether market prices should be ignored for the commodity.
+    pub nomarket: bool,
+    /// Whether this is the default commodity.
+    pub default: bool,
+    pub file: Option<&'a Path>,
+    /// 1-based line of the `commodity` line.
+    pub line: usize,
+}
+
+/// A market price from a `P` directive such as `P 2024-03-01 AAPL 172.50 USD`:
+/// on `date`, one unit of `commodity` was worth `price`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Price<'a> {
+    pub date: NaiveDate,
+    pub commodity: &'a str,
+    pub price: Amount<'a>,
+    pub file: Option<&'a Path>,
+    /// 1-based line of the directive.
+    pub line: usize,
+}
+
+enum Entry<'a> {
+    Transaction(Transaction<'a>),
+    Comment(&'a str),
+    DecimalMark(char),
+    Account(AccountDeclaration<'a>),
+    Commodity(CommodityDeclaration<'a>),
+    Include(&'a str),
+    Price(Price<'a>),
+}
+
+fn line_comment(input: &str) -> IResult<'_, &str> {
+    recognize(pair(one_of(";#%|*"), not_line_ending))(input)
+}
+
+/// A `comment` ... `end comment` block. A block that is never closed runs to
+/// the end of the input.
+fn block_comment(input: &str) -> IResult<'_, &str> {
+    recognize(tuple((
+        tag("comment"),
+        end_of_line,
+        alt((
+            recognize(pair(take_until("\nend comment"), tag("\nend comment"))),
+            rest,
+        )),
+        not_line_ending,
+    )))(input)
+}
+
+/// A `decimal-mark ,` directive, fixing the decimal mark of the amounts that
+/// follow it.
+fn decimal_mark(input: &str) -> IResult<'_, char> {
+    preceded(pair(tag("decimal-mark"), space1), one_of(",."))(input)
+}
+
+/// The text of a directive line, up to a trailing comment.
+fn directive_argument(input: &str) -> IResult<'_, &str> {
+    map(
+        terminated(
+            take_till(|c| c == ';' || c == '\r' || c == '\n'),
+            opt(comment),
+        ),
+        str::trim_end,
+    )(input)
+}
+
+fn account_declaration(input: &str) -> IResult<'_, AccountDeclaration<'_>> {
+    let (mut input, name) = delimited(
+        pair(tag("account"), space1),
+        context("account", account_name(" \t\r\n")),
+        end_of_directive,
     )(input)?;
-    Ok((
-        input,
-        Transaction {
-            date,
-            auxillary_date,
-            state,
-            code,
-            merchant,
-            memo,
-            postings,
-        },
+    let mut declaration = AccountDeclaration {
+        name,
+        ..Default::default()
+    };
+    while let Ok((line, _)) = indented_line(input) {
+        if let Ok((rest, _)) = comment(line) {
+            input = rest;
+            continue;
+        }
+        let (rest, (keyword, argument)) = cut(context(
+            "account subdirective",
+            pair(
+                terminated(
+                    alt((
+                        tag("note"),
+                        tag("alias"),
+                        tag("type"),
+                        tag("assert"),
+                        tag("check"),
+                    )),
+                    space1,
+                ),
+                directive_argument,
+            ),
+        ))(line)?;
+        match keyword {
+            "note" => declaration.note = Some(argument),
+            "alias" => declaration.aliases.push(argument),
+            "type" => declaration.account_type = Some(argument),
+            "assert" => declaration.asserts.push(argument),
+            _ => declaration.checks.push(argument),
+        }
+        input = rest;
+    }
+    Ok((input, declaration))
+}
+
+/// Trailing whitespace and an optional comment at the end of a directive.
+fn end_of_directive(input: &str) -> IResult<'_, ()> {
+    value((), tuple((space0, opt(comment), end_of_line)))(input)
+}
+
+fn commodity_declaration<'a>(
+    format: NumberFormat,
+) -> impl FnMut(&'a str) -> IResult<'a, CommodityDeclaration<'a>> {
+    move |input| {
+        let (input, _) = pair(tag("commodity"), space1)(input)?;
+        let (mut input, (symbol, style)) = terminated(
+            alt((
+                map(amount_with(format), |amount| {
+                    (amount.currency, Some(amount.style))
+                }),
+                map(context("commodity", commodity), |symbol| (symbol, None)),
+            )),
+            end_of_directive,
+        )(input)?;
+        let mut declaration = CommodityDeclaration {
+            symbol,
+            format: style,
+            ..Default::default()
+        };
+        while let Ok((line, _)) = indented_line(input) {
+            if let Ok((rest, _)) = comment(line) {
+                input = rest;
+                continue;
+            }
+            let (rest, keyword) = cut(context(
+                "commodity subdirective",
+                alt((tag("note"), tag("format"), tag("nomarket"), tag("default"))),
+            ))(line)?;
+            let (rest, _) = match keyword {
+                "note" => {
+                    let (rest, note) = preceded(space1, directive_argument)(rest)?;
+                    declaration.note = Some(note);
+                    (rest, ())
+                }
+                "format" => {
+                    let (rest, amount) = cut(preceded(space1, amount_with(format)))(rest)?;
+                    declaration.format = Some(amount.style);
+                    cut(end_of_directive)(rest)?
+                }
+                "nomarket" => {
+                    declaration.nomarket = true;
+                    cut(end_of_directive)(rest)?
+                }
+                _ => {
+                    declaration.default = true;
+                    cut(end_of_directive)(rest)?
+                }
+            };
+            input = rest;
+        }
+        Ok((input, declaration))
+    }
+}
+
+/// An `include PATH` directive. The path may be relative to the including
+/// file and may contain glob patterns.
+fn include(input: &str) -> IResult<'_, &str> {
+    preceded(
+        pair(tag("include"), space1),
+        context(
+            "path",
+            verify(directive_argument, |path: &str| !path.is_empty()),
+        ),
+    )(input)
+}
+
+/// A time of day such as `12:30` or `12:30:00`.
+fn time(input: &str) -> IResult<'_, &str> {
+    recognize(tuple((
+        digit1,
+        char(':'),
+        digit1,
+        opt(pair(char(':'), digit1)),
+    )))(input)
+}
+
+fn price_directive<'a>(format: NumberFormat) -> impl FnMut(&'a str) -> IResult<'a, Price<'a>> {
+    move |input| {
+        let (input, _) = pair(char('P'), space1)(input)?;
+        // The time of day is accepted for compatibility but not kept.
+        let (input, (date, _)) = cut(pair(
+            terminated(date, space1),
+            opt(terminated(time, space1)),
+        ))(input)?;
+        let (input, commodity) = cut(terminated(context("commodity", commodity), space1))(input)?;
+        let (input, price) = cut(terminated(amount_with(format), end_of_directive))(input)?;
+        Ok((
+            input,
+            Price {
+                date,
+                commodity,
+                price,
+                file: None,
+                line: 0,
+            },
+        ))
+    }
+}
+
+fn entry<'a>(format: NumberFormat) -> impl FnMut(&'a str) -> IResult<'a, Entry<'a>> {
+    alt((
+        map(alt((line_comment, block_comment)), Entry::Comment),
+        map(terminated(decimal_mark, end_of_line), Entry::DecimalMark),
+        map(account_declaration, Entry::Account),
+        map(commodity_declaration(format), Entry::Commodity),
+        map(terminated(include, end_of_line), Entry::Include),
+        map(price_directive(format), Entry::Price),
+        map(transaction_with(format), Entry::Transaction),
     ))
 }
 
+impl<'a> Journal<'a> {
+    fn push(&mut self, entry: Entry<'a>, file: Option<&'a Path>, line: usize) {
+        match entry {
+            Entry::Transaction(mut transaction) => {
+                transaction.file = file;
+                transaction.line = line;
+                for posting in &mut transaction.postings {
+                    posting.line += line;
+                    let alias = self
+                        .accounts
+                        .iter()
+                        .find(|account| account.aliases.contains(&posting.account.name));
+                    if let Some(account) = alias {
+                        posting.account.name = account.name;
+                    }
+                }
+                self.transactions.push(transaction);
+            }
+            Entry::Comment(text) => self.comments.push(Comment { file, line, text }),
+            Entry::DecimalMark(_) | Entry::Include(_) => {}
+            Entry::Account(mut declaration) => {
+                declaration.file = file;
+                declaration.line = line;
+                self.accounts.push(declaration);
+            }
+            Entry::Commodity(mut declaration) => {
+                declaration.file = file;
+                declaration.line = line;
+                self.commodities.push(declaration);
+            }
+            Entry::Price(mut price) => {
+                price.file = file;
+                price.line = line;
+                self.prices.push(price);
+            }
+        }
+    }
+}
+
+fn blank_lines(input: &str) -> IResult<'_, ()> {
+    value((), many0(pair(space0, line_ending)))(input)
+}
+
+/// Counts the lines between `from` and `to`, a suffix of `from`.
+fn lines_between(from: &str, to: &str) -> usize {
+    from[..from.len() - to.len()].matches('\n').count()
+}
+
+/// `input` without the byte order mark some Windows programs put at the start
+/// of UTF-8 files.
+fn without_bom(input: &str) -> &str {
+    input.strip_prefix('\u{feff}').unwrap_or(input)
+}
+
+fn skip_blank_lines(input: &str) -> &str {
+    pair(blank_lines, space0)(input).map_or(input, |(rest, _)| rest)
+}
+
+/// Skips past the line `input` starts on and any following lines that are
+/// indented or blank, i.e. to where the next entry could begin.
+fn skip_to_next_entry(mut input: &str) -> &str {
+    loop {
+        input = match input.find('\n') {
+            Some(end) => &input[end + 1..],
+            None => return "",
+        };
+        if !input.starts_with([' ', '\t', '\r', '\n']) {
+            return input;
+        }
+    }
+}
+
+/// A journal being built up entry by entry, possibly from several files.
+#[derive(Default)]
+pub(crate) struct Builder<'a> {
+    pub(crate) journal: Journal<'a>,
+    /// The number format set by the last `decimal-mark` directive.
+    format: NumberFormat,
+}
+
+impl<'a> Builder<'a> {
+    /// Adds the entries of `input`, read from `file`, to the journal.
+    ///
+    /// `recover` decides whether to skip to the next unindented line or to
+    /// give up with that error. `include` is called with the path of every
+    /// `include` directive, and adds the entries of the files it names.
+    pub(crate) fn entries(
+        &mut self,
+        mut input: &'a str,
+        file: Option<&'a Path>,
+        mut recover: impl FnMut(&Error<'a>) -> bool,
+        mut include: impl FnMut(&mut Self, &'a str) -> Result<(), Reason>,
+    ) -> IResult<'a, ()> {
+        input = without_bom(input);
+        let mut line = 1;
+        loop {
+            let start = skip_blank_lines(input);
+            line += lines_between(input, start);
+            if start.is_empty() {
+                return Ok((start, ()));
+            }
+            input = match entry(self.format)(start) {
+                Ok((rest, entry)) => {
+                    match entry {
+                        Entry::DecimalMark(mark) => self.format.decimal_mark = Some(mark),
+                        Entry::Include(path) => {
+                            if let Err(reason) = include(self, path) {
+                                let e = Error::new(start, reason);
+                                if !recover(&e) {
+                                    return Err(nom::Err::Failure(e));
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                    self.journal.push(entry, file, line);
+                    rest
+                }
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) if recover(&e) => {
+                    skip_to_next_entry(start)
+                }
+                Err(e) => return Err(e),
+            };
+            line += lines_between(start, input);
+        }
+    }
+}
+
+/// The paths of the `include` directives in `source`, in order, without
+/// following them.
+pub(crate) fn include_paths(source: &str) -> Vec<&str> {
+    let mut paths = Vec::new();
+    let _ = Builder::default().entries(
+        source,
+        None,
+        |_| true,
+        |_, path| {
+            paths.push(path);
+            Ok(())
+        },
+    );
+    paths
+}
+
+/// Parses every transaction and comment in a journal, in file order. Unlike
+/// [`transaction`], this consumes the whole input: anything that is not a
+/// transaction is reported as an error at the point it starts.
+pub fn journal(input: &str) -> IResult<'_, Journal<'_>> {
+    let mut builder = Builder::default();
+    let (input, _) = builder.entries(input, None, |_| false, unresolved_include)?;
+    Ok((input, builder.journal))
+}
+
+fn unresolved_include<'a>(_: &mut Builder<'a>, _: &'a str) -> Result<(), Reason> {
+    Err(Reason::UnresolvedInclude)
+}
+
+/// Parses a complete journal, resolving any failure to a line and column in
+/// `source`.
+pub fn parse(source: &str) -> Result<Journal<'_>, ParseError> {
+    journal(source)
+        .finish()
+        .map(|(_, journal)| journal)
+        .map_err(|e| e.locate(source))
+}
+
+/// Parses as much of a journal as possible. Every malformed entry is
+/// recorded as a diagnostic and skipped, and parsing resumes at the next line
+/// that is not indented, so a single pass reports every broken entry.
+pub fn parse_recovering(source: &str) -> (Journal<'_>, Vec<ParseError>) {
+    let mut diagnostics = Vec::new();
+    let mut builder = Builder::default();
+    let recover = |e: &Error| {
+        diagnostics.push(e.locate(source));
+        true
+    };
+    match builder.entries(source, None, recover, unresolved_include) {
+        Ok(_) => (builder.journal, diagnostics),
+        Err(_) => unreachable!("every error is recovered from"),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
-    fn test_and_extract<'a, T, F: Fn(&'a str) -> IResult<&'a str, T>>(input: &'a str, f: F) -> T {
+    fn test_and_extract<'a, T, F: Fn(&'a str) -> IResult<'a, T>>(input: &'a str, f: F) -> T {
         let (_, out) = f(input).unwrap();
         out
     }
@@ -154,33 +1135,200 @@ mod test {
         assert_eq!(
             Amount {
                 currency: "USD",
-                amount: Decimal::new(2000, 2)
+                amount: Decimal::new(2000, 2),
+                ..Default::default()
             },
             test_and_extract("USD 20", amount)
         );
         assert_eq!(
             Amount {
                 currency: "USD",
-                amount: Decimal::new(2000, 2)
+                amount: Decimal::new(2000, 2),
+                ..Default::default()
             },
             test_and_extract("20.00 USD", amount)
         );
         assert_eq!(
             Amount {
                 currency: "USD",
-                amount: Decimal::new(2000, 2)
+                amount: Decimal::new(2000, 2),
+                ..Default::default()
             },
             test_and_extract("USD20.00", amount)
         );
         assert_eq!(
             Amount {
                 currency: "USD",
-                amount: Decimal::new(2000, 2)
+                amount: Decimal::new(2000, 2),
+                ..Default::default()
             },
             test_and_extract("20USD", amount)
         );
     }
 
+    #[test]
+    fn parse_signed_amount() {
+        let amount_of = |input| {
+            let parsed = test_and_extract(input, amount);
+            (parsed.currency, parsed.amount)
+        };
+        assert_eq!(amount_of("-20.00 USD"), ("USD", Decimal::new(-2000, 2)));
+        assert_eq!(
+            amount_of("USD -1,234.56"),
+            ("USD", Decimal::new(-123456, 2))
+        );
+        assert_eq!(amount_of("-USD 5"), ("USD", Decimal::new(-5, 0)));
+        assert_eq!(amount_of("+5 USD"), ("USD", Decimal::new(5, 0)));
+        assert_eq!(amount_of("1.234,56 EUR"), ("EUR", Decimal::new(123456, 2)));
+        let style = test_and_extract("1.234,56 EUR", amount).style;
+        assert_eq!(style.decimal_mark, ',');
+        assert_eq!(style.digit_group_mark, Some('.'));
+        assert_eq!(style.precision, 2);
+        for input in ["-$-5", "-USD -5", "+USD -5"] {
+            let Err(nom::Err::Failure(err)) = amount(input) else {
+                panic!("{} should not parse", input);
+            };
+            assert_eq!(err.reason, Reason::InvalidNumber);
+        }
+    }
+
+    #[test]
+    fn parse_commodities() {
+        let written = |input| {
+            let parsed = test_and_extract(input, amount);
+            (
+                parsed.currency,
+                parsed.amount,
+                parsed.style.commodity_side,
+                parsed.style.commodity_spaced,
+            )
+        };
+        assert_eq!(
+            written("$20"),
+            ("$", Decimal::new(20, 0), Side::Left, false)
+        );
+        assert_eq!(
+            written("-$20.00"),
+            ("$", Decimal::new(-2000, 2), Side::Left, false)
+        );
+        assert_eq!(
+            written("20 €"),
+            ("€", Decimal::new(20, 0), Side::Right, true)
+        );
+        assert_eq!(
+            written("10 \"VTSAX 2\""),
+            ("VTSAX 2", Decimal::new(10, 0), Side::Right, true)
+        );
+        assert_eq!(
+            written("BRK.B 3"),
+            ("BRK.B", Decimal::new(3, 0), Side::Left, true)
+        );
+        assert_eq!(
+            written("3 BRK.B"),
+            ("BRK.B", Decimal::new(3, 0), Side::Right, true)
+        );
+    }
+
+    #[test]
+    fn parse_decimal_mark_directive() {
+        let j = "2024-03-01 Shop\n\tExpenses:Food  EUR 1,234\n\tAssets:Cash\n\ndecimal-mark ,\n\n2024-03-02 Shop\n\tExpenses:Food  EUR 1,234\n\tAssets:Cash\n";
+        let parsed = parse(j).unwrap();
+        let amounts: Vec<_> = parsed
+            .transactions
+            .iter()
+            .map(|t| t.postings[0].amount.as_ref().unwrap().amount)
+            .collect();
+        assert_eq!(amounts, vec![Decimal::new(1234, 0), Decimal::new(1234, 3)]);
+    }
+
+    #[test]
+    fn parse_posting_cost() {
+        let p = test_and_extract("Assets:Broker  10 AAPL @ 150 USD ; bought", posting);
+        assert_eq!(p.amount, Some(Amount::new("AAPL", Decimal::new(10, 0))));
+        assert_eq!(
+            p.cost,
+            Some(Cost::Unit(Amount::new("USD", Decimal::new(150, 0))))
+        );
+        assert_eq!(p.comments, vec!["bought"]);
+        let p = test_and_extract("Assets:Broker  10 AAPL @@ $1,500", posting);
+        assert_eq!(
+            p.cost,
+            Some(Cost::Total(Amount::new("$", Decimal::new(1500, 0))))
+        );
+        assert!(posting("Assets:Broker  10 AAPL @ ").is_err());
+    }
+
+    #[test]
+    fn parse_lot() {
+        let p = test_and_extract(
+            "Assets:Broker  10 AAPL {150 USD} [2024-01-03] (lot-a) @ 160 USD",
+            posting,
+        );
+        assert_eq!(
+            p.lot,
+            Some(Lot {
+                cost: Some(Cost::Unit(Amount::new("USD", Decimal::new(150, 0)))),
+                date: NaiveDate::from_ymd_opt(2024, 1, 3),
+                note: Some("lot-a"),
+            })
+        );
+        assert_eq!(
+            p.cost,
+            Some(Cost::Unit(Amount::new("USD", Decimal::new(160, 0))))
+        );
+        let p = test_and_extract("Assets:Broker  10 AAPL (lot-b) {{ 1500 USD }}", posting);
+        assert_eq!(
+            p.lot,
+            Some(Lot {
+                cost: Some(Cost::Total(Amount::new("USD", Decimal::new(1500, 0)))),
+                date: None,
+                note: Some("lot-b"),
+            })
+        );
+        assert_eq!(
+            test_and_extract("Assets:Broker  10 AAPL", posting).lot,
+            None
+        );
+        assert!(posting("Assets:Broker  10 AAPL {150 USD").is_err());
+    }
+
+    #[test]
+    fn parse_balance_assertion() {
+        let p = test_and_extract("Assets:Checking  USD 0 = USD 1523.10", posting);
+        assert_eq!(p.amount, Some(Amount::new("USD", Decimal::ZERO)));
+        assert_eq!(
+            p.assertion,
+            Some(BalanceAssertion {
+                amount: Amount::new("USD", Decimal::new(152310, 2)),
+                total: false,
+                inclusive: false,
+            })
+        );
+        let p = test_and_extract("Assets  ==* $5 ; everything", posting);
+        assert_eq!(p.amount, None);
+        assert_eq!(
+            p.assertion,
+            Some(BalanceAssertion {
+                amount: Amount::new("$", Decimal::new(5, 0)),
+                total: true,
+                inclusive: true,
+            })
+        );
+        assert_eq!(p.comments, vec!["everything"]);
+    }
+
+    #[test]
+    fn parse_virtual_posting() {
+        let p = test_and_extract("(Budget:Food)  USD -20", posting);
+        assert_eq!(p.kind, PostingKind::Virtual);
+        assert_eq!(p.account.name, "Budget:Food");
+        let p = test_and_extract("[Budget:Eating Out]", posting);
+        assert_eq!(p.kind, PostingKind::BalancedVirtual);
+        assert_eq!(p.account.name, "Budget:Eating Out");
+        let p = test_and_extract("Expenses:Food  USD 20", posting);
+        assert_eq!(p.kind, PostingKind::Real);
+    }
+
     #[test]
     fn parse_transaction_state() {
         assert_eq!(
@@ -197,6 +1345,37 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_posting_state_and_dates() {
+        let (_, t) = transaction("2024-03-01 ! Shop\n\t* Expenses:Food  USD 20 ; [2024-03-05]\n\tLiabilities:Credit ; [=2024-03-07]\n\t; date: 2024-03-06\n\tAssets:Cash  USD 0").unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        let food = &t.postings[0];
+        assert_eq!(food.account.name, "Expenses:Food");
+        assert_eq!(food.state, TransactionState::Cleared);
+        assert_eq!(food.effective_date(&t), day(5));
+        assert_eq!(food.effective_auxillary_date(&t), None);
+        let credit = &t.postings[1];
+        assert_eq!(credit.effective_state(&t), TransactionState::Pending);
+        assert_eq!(credit.effective_date(&t), day(6));
+        assert_eq!(credit.effective_auxillary_date(&t), Some(day(7)));
+        assert_eq!(t.postings[2].effective_date(&t), day(1));
+
+        let error =
+            parse("2024-03-01 Shop\n\tExpenses:Food  USD 20 ; date: 2024-02-30\n\tAssets:Cash\n")
+                .unwrap_err();
+        assert_eq!((error.line, error.reason), (2, Reason::InvalidDate));
+
+        let error =
+            parse("2024-03-01 Shop\n\tExpenses:Food  USD 20\n\tAssets:Cash  ; [2024-02-30]\n")
+                .unwrap_err();
+        assert_eq!(
+            (error.line, error.column, error.reason),
+            (3, 18, Reason::InvalidDate)
+        );
+        let (_, t) = transaction("2024-03-01 Shop\n\tAssets:Cash  USD 1 ; [not a date]").unwrap();
+        assert_eq!(t.postings[0].date, None);
+    }
+
     #[test]
     fn parse_date() {
         assert_eq!(
@@ -224,6 +1403,10 @@ mod test {
             (Some("foo"), "bar"),
             test_and_extract("foo | bar", description)
         );
+        assert_eq!(
+            (Some("foo"), "bar"),
+            test_and_extract("   foo  |   bar  ", description)
+        );
     }
 
     #[test]
@@ -235,9 +1418,12 @@ mod test {
             amount: Some(Amount {
                 currency: "USD",
                 amount: Decimal::new(2000, 2),
+                ..Default::default()
             }),
+            ..Default::default()
         };
         assert_eq!(p, test_and_extract("Expenses:Food  USD20.00", posting));
+        assert_eq!(p, test_and_extract("Expenses:Food\tUSD20.00", posting));
     }
 
     #[test]
@@ -262,16 +1448,275 @@ mod test {
                     },
                     amount: Some(Amount {
                         currency: "USD",
-                        amount: Decimal::new(2000, 2)
-                    })
+                        amount: Decimal::new(2000, 2),
+                        ..Default::default()
+                    }),
+                    line: 1,
+                    ..Default::default()
                 },
                 Posting {
                     account: Account {
                         name: "Liabilities:Credit"
                     },
-                    amount: None
+                    amount: None,
+                    line: 2,
+                    ..Default::default()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_space_indentation() {
+        let j = "2024-03-01 Shop\n  Expenses:Food  USD 20\n    ; lunch\n  Assets:Cash\n  \n\ncommodity USD\n    note US dollars\n";
+        let parsed = parse(j).unwrap();
+        let parsed_transaction = &parsed.transactions[0];
+        assert_eq!(parsed_transaction.postings.len(), 2);
+        assert_eq!(parsed_transaction.postings[0].comments, vec!["lunch"]);
+        assert_eq!(parsed_transaction.postings[1].line, 4);
+        assert!(!parsed_transaction.mixed_indentation);
+        assert_eq!(parsed.commodities[0].note, Some("US dollars"));
+
+        let t = "2024-03-01 Shop\n\tExpenses:Food  USD 20\n \tAssets:Cash";
+        assert!(test_and_extract(t, transaction).mixed_indentation);
+    }
+
+    #[test]
+    fn parse_journal() {
+        let j = "\n2024-03-01 Rent\n\tExpenses:Rent  USD1000\n\tAssets:Checking\n\n\n2024-03-02 * Grocer | Weekly shop\n\tExpenses:Food  USD20.00\n\tLiabilities:Credit\n";
+        let (rest, parsed) = journal(j).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(parsed.transactions.len(), 2);
+        assert_eq!(parsed.transactions[0].memo, "Rent");
+        assert_eq!(parsed.transactions[0].line, 2);
+        assert_eq!(parsed.transactions[1].line, 7);
+        assert_eq!(
+            parsed.transactions[0].postings[1].account,
+            Account {
+                name: "Assets:Checking"
+            }
+        );
+        assert_eq!(parsed.transactions[1].merchant, Some("Grocer"));
+        assert_eq!(parsed.transactions[1].postings.len(), 2);
+    }
+
+    #[test]
+    fn parse_journal_trailing_input() {
+        let j =
+            "2024-03-01 Rent\n\tExpenses:Rent  USD1000\n\tAssets:Checking\n\nnot a transaction\n";
+        let err = parse(j).unwrap_err();
+        assert_eq!(err.line, 5);
+        assert_eq!(err.column, 1);
+        assert_eq!(err.snippet, "not a transaction");
+        assert_eq!(err.reason, Reason::Expected("date"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = parse("2024-02-30 Rent\n\tExpenses:Rent  USD1000\n").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+        assert_eq!(err.reason, Reason::InvalidDate);
+
+        let err = parse("2024-02-01 Rent\n\tExpenses:Rent  1000\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 17));
+        assert_eq!(err.reason, Reason::Expected("amount"));
+
+        let err = parse("2024-02-01 Rent\n\tExpenses:Rent  USD1000 extra\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 24));
+        assert_eq!(err.reason, Reason::Expected("end of line"));
+
+        let err = parse("2024-02-01 Rent\n\tExpenses:Rent  USD99999999999999999999999999999999\n")
+            .unwrap_err();
+        assert_eq!((err.line, err.column), (2, 17));
+        assert_eq!(err.reason, Reason::InvalidNumber);
+    }
+
+    #[test]
+    fn parse_journal_recovering() {
+        let j = "2024-03-01 Rent\n\tExpenses:Rent  USD1000\n\tAssets:Checking\n\n2024-03-02 Broken\n\tExpenses:Food  twenty\n\tAssets:Checking\n\n2024-02-30 Bad date\n\tExpenses:Food  USD1\n\tAssets:Checking\n2024-03-04 Grocer\n\tExpenses:Food  USD20\n\tAssets:Checking\n";
+        let (parsed, diagnostics) = parse_recovering(j);
+        assert_eq!(
+            parsed
+                .transactions
+                .iter()
+                .map(|t| t.memo)
+                .collect::<Vec<_>>(),
+            vec!["Rent", "Grocer"]
+        );
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.line, d.reason.clone()))
+                .collect::<Vec<_>>(),
+            vec![(6, Reason::Expected("amount")), (9, Reason::InvalidDate)]
+        );
+    }
+
+    #[test]
+    fn parse_recovering_keeps_directives() {
+        let j = "2024-03-01 Broken\n\tExpenses:Food  twenty\naccount Expenses:Food\ncommodity USD\nP 2024-03-01 EUR 1.1 USD\nnot an entry\n\t  still skipped\n\n2024-03-02 Shop\n\tExpenses:Food  USD 1\n\tAssets:Cash\n";
+        let (parsed, diagnostics) = parse_recovering(j);
+        assert_eq!(parsed.accounts.len(), 1);
+        assert_eq!(parsed.commodities.len(), 1);
+        assert_eq!(parsed.prices.len(), 1);
+        assert_eq!(parsed.transactions.len(), 1);
+        assert_eq!(
+            diagnostics.iter().map(|d| d.line).collect::<Vec<_>>(),
+            vec![2, 6]
+        );
+    }
+
+    #[test]
+    fn parse_transaction_comments() {
+        let t = "2024-03-01 * Grocer | Weekly shop  ; header note\n\t; about the shop\n\tExpenses:Food  USD20.00 ; posting note\n\t; more about food\n\tLiabilities:Credit  ;no amount";
+        let parsed = test_and_extract(t, transaction);
+        assert_eq!(parsed.merchant, Some("Grocer"));
+        assert_eq!(parsed.memo, "Weekly shop");
+        assert_eq!(parsed.comments, vec!["header note", "about the shop"]);
+        assert_eq!(
+            parsed.postings[0].comments,
+            vec!["posting note", "more about food"]
+        );
+        assert_eq!(
+            parsed.postings[1],
+            Posting {
+                account: Account {
+                    name: "Liabilities:Credit"
+                },
+                amount: None,
+                comments: vec!["no amount"],
+                trailing_comment: true,
+                line: 4,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_journal_comments() {
+        let j = "; top of file\n# hash\n%percent\n| bar\n* star\ncomment\n2024-01-01 not a transaction\nend comment\n\n2024-03-01 Rent\n\tExpenses:Rent  USD1000\n\tAssets:Checking\n; trailing\n";
+        let parsed = parse(j).unwrap();
+        assert_eq!(parsed.transactions.len(), 1);
+        assert_eq!(parsed.transactions[0].line, 10);
+        assert_eq!(
+            parsed
+                .comments
+                .iter()
+                .map(|c| (c.line, c.text))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, "; top of file"),
+                (2, "# hash"),
+                (3, "%percent"),
+                (4, "| bar"),
+                (5, "* star"),
+                (6, "comment\n2024-01-01 not a transaction\nend comment"),
+                (13, "; trailing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_account_directive() {
+        let j = "account Expenses:Food  ; groceries\n\tnote Groceries and eating out\n\t; ignored\n\talias food\n\ttype Expense\n\tassert commodity == \"USD\"\n\tcheck abs(amount) < 1000\n\n2024-03-01 Shop\n\tfood  USD 20\n\tAssets:Cash\n";
+        let parsed = parse(j).unwrap();
+        assert_eq!(
+            parsed.accounts,
+            vec![AccountDeclaration {
+                name: "Expenses:Food",
+                note: Some("Groceries and eating out"),
+                aliases: vec!["food"],
+                account_type: Some("Expense"),
+                asserts: vec!["commodity == \"USD\""],
+                checks: vec!["abs(amount) < 1000"],
+                file: None,
+                line: 1,
+            }]
+        );
+        assert_eq!(
+            parsed.transactions[0].postings[0].account.name,
+            "Expenses:Food"
+        );
+
+        let error = parse("account Assets\n\tcolour blue\n").unwrap_err();
+        assert_eq!(
+            (error.line, error.column, error.reason),
+            (2, 2, Reason::Expected("account subdirective"))
+        );
+    }
+
+    #[test]
+    fn parse_commodity_directive() {
+        let j = "commodity USD\n\tnote US dollars ; comment\n\tformat USD 1,000.00\n\tnomarket\n\tdefault\ncommodity 1.000,0 EUR\n";
+        let parsed = parse(j).unwrap();
+        assert_eq!(
+            parsed.commodities,
+            vec![
+                CommodityDeclaration {
+                    symbol: "USD",
+                    note: Some("US dollars"),
+                    format: Some(AmountStyle {
+                        digit_group_mark: Some(','),
+                        precision: 2,
+                        ..Default::default()
+                    }),
+                    nomarket: true,
+                    default: true,
+                    file: None,
+                    line: 1,
+                },
+                CommodityDeclaration {
+                    symbol: "EUR",
+                    format: Some(AmountStyle {
+                        commodity_side: Side::Right,
+                        decimal_mark: ',',
+                        digit_group_mark: Some('.'),
+                        precision: 1,
+                        ..Default::default()
+                    }),
+                    line: 6,
+                    ..Default::default()
+                }
+            ]
+        );
+
+        let error = parse("commodity USD\n\tformat 12\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 9));
+    }
+
+    #[test]
+    fn parse_include_without_file() {
+        let error = parse("include accounts.ledger ; shared\n").unwrap_err();
+        assert_eq!(
+            (error.line, error.column, error.reason),
+            (1, 1, Reason::UnresolvedInclude)
+        );
+    }
+
+    #[test]
+    fn parse_price_directive() {
+        let parsed =
+            parse("P 2024-03-01 AAPL 172.50 USD\nP 2024/03/02 12:30:00 EUR $1.08 ; ecb\n").unwrap();
+        assert_eq!(
+            parsed.prices,
+            vec![
+                Price {
+                    date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+                    commodity: "AAPL",
+                    price: Amount::new("USD", Decimal::new(17250, 2)),
+                    file: None,
+                    line: 1,
+                },
+                Price {
+                    date: NaiveDate::from_ymd_opt(2024, 3, 2).unwrap(),
+                    commodity: "EUR",
+                    price: Amount::new("$", Decimal::new(108, 2)),
+                    file: None,
+                    line: 2,
                 }
             ]
         );
+        let error = parse("P 2024-03-01 AAPL\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 18));
     }
 }
@@ -1,11 +1,272 @@
-use plain_text_accounting::transaction;
-use std::fs::File;
-use std::io::prelude::*;
-fn main() -> std::io::Result<()> {
-    let mut file = File::open("journal.ledger")?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let (_, t) = transaction(&contents).unwrap();
-    println!("{:#?}", t);
-    Ok(())
+use chrono::NaiveDate;
+use plain_text_accounting::{
+    BalanceOptions, BalanceReport, Imbalance, Query, RegisterOptions, RegisterReport, Sources,
+    SyntaxTree, TransactionState, Valuation, Valuer,
+};
+use similar::TextDiff;
+use std::fmt::Display;
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: plain-text-accounting [-f FILE] [--strict] [COMMAND] [OPTIONS]
+
+Without a command, prints the parsed journal. FILE defaults to journal.ledger.
+
+commands:
+  balance   account balances as a tree
+            --depth N  --flat  --empty
+  register  matching postings with a running total
+            --aux-date  --sort-date  --collapse
+  fmt       rewrite FILE with ISO dates, tab indentation and aligned amounts
+            --column N  end amounts at column N after the indentation
+            --check     only print a diff and fail if FILE is not formatted
+
+query options:
+  --begin DATE  --end DATE  --real  --cleared  --pending  --uncleared
+  --tag TAG[=VALUE]
+
+valuation options:
+  -X COMMODITY         convert amounts to COMMODITY
+  --value cost|end|then|DATE
+                       which prices to convert at, defaults to end";
+
+/// Prints each error prefixed with the file it was found in.
+fn report<'a, E: Display>(errors: &[E], file: impl Fn(&E) -> Option<&'a Path>, root: &Path) {
+    for error in errors {
+        eprintln!("{}: {}", file(error).unwrap_or(root).display(), error);
+    }
+}
+
+#[derive(Default)]
+struct Args {
+    file: Option<String>,
+    strict: bool,
+    command: Option<String>,
+    /// Options left for the command, as `(flag, value)` pairs.
+    options: Vec<(String, Option<String>)>,
+}
+
+/// Flags that take a value.
+const VALUED: &[&str] = &[
+    "-f", "--depth", "--begin", "--end", "--tag", "-X", "--value", "--column",
+];
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut input = std::env::args().skip(1);
+    while let Some(arg) = input.next() {
+        let value = match VALUED.contains(&arg.as_str()) {
+            true => Some(input.next().ok_or(format!("{} needs a value", arg))?),
+            false => None,
+        };
+        match (arg.as_str(), value) {
+            ("-f", file) => args.file = file,
+            ("--strict", _) => args.strict = true,
+            (flag, value) if flag.starts_with('-') => args.options.push((arg.clone(), value)),
+            (_, _) if args.command.is_none() && is_command(&arg) => args.command = Some(arg),
+            // A bare path is accepted in place of `-f`.
+            (_, _) if args.file.is_none() => args.file = Some(arg),
+            (_, _) => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    Ok(args)
+}
+
+fn is_command(arg: &str) -> bool {
+    matches!(arg, "balance" | "bal" | "register" | "reg" | "fmt")
+}
+
+fn date(text: &str) -> Result<NaiveDate, String> {
+    match plain_text_accounting::date(text) {
+        Ok(("", date)) => Ok(date),
+        _ => Err(format!("invalid date {}", text)),
+    }
+}
+
+/// The query, valuation and report options of a command.
+struct Options<'a> {
+    query: Query<'a>,
+    target: Option<&'a str>,
+    valuation: Valuation,
+    balance: BalanceOptions,
+    register: RegisterOptions,
+    column: Option<usize>,
+    check: bool,
+}
+
+fn parse_options(options: &[(String, Option<String>)]) -> Result<Options<'_>, String> {
+    let mut query = Query::new();
+    let mut target = None;
+    let mut valuation = Valuation::End;
+    let mut balance = BalanceOptions::new();
+    let mut register = RegisterOptions::new();
+    let mut column = None;
+    let mut check = false;
+    for (flag, value) in options {
+        let value = value.as_deref().unwrap_or_default();
+        match flag.as_str() {
+            "--begin" => query = query.begin(date(value)?),
+            "--end" => query = query.end(date(value)?),
+            "--real" => query = query.real(),
+            "--cleared" => query = query.state(TransactionState::Cleared),
+            "--pending" => query = query.state(TransactionState::Pending),
+            "--uncleared" => query = query.state(TransactionState::Uncleared),
+            "--tag" => {
+                query = match value.split_once('=') {
+                    Some((key, value)) => query.tag_value(key, value),
+                    None => query.tag(value),
+                }
+            }
+            "-X" => target = Some(value),
+            "--value" => {
+                valuation = match value {
+                    "cost" => Valuation::Cost,
+                    "end" => Valuation::End,
+                    "then" => Valuation::Then,
+                    _ => Valuation::At(date(value)?),
+                }
+            }
+            "--depth" => balance = balance.depth(value.parse().map_err(|_| "invalid depth")?),
+            "--flat" => balance = balance.flat(),
+            "--empty" => balance = balance.empty(),
+            "--aux-date" => register = register.auxillary_date(),
+            "--sort-date" => register = register.sort_by_date(),
+            "--collapse" => register = register.collapse(),
+            "--column" => column = Some(value.parse().map_err(|_| "invalid column")?),
+            "--check" => check = true,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    Ok(Options {
+        query,
+        target,
+        valuation,
+        balance,
+        register,
+        column,
+        check,
+    })
+}
+
+/// Rewrites the journal at `path` in canonical form. With `check`, leaves it
+/// alone and prints how it would change instead, failing if it would.
+fn format(path: &Path, column: Option<usize>, check: bool) -> ExitCode {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let formatted = match SyntaxTree::parse(&source) {
+        Ok(tree) => tree.formatted(column),
+        Err(mut error) => {
+            error.file = Some(path.to_path_buf());
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    if formatted == source {
+        return ExitCode::SUCCESS;
+    }
+    if check {
+        let name = path.display().to_string();
+        let diff = TextDiff::from_lines(&source, &formatted);
+        print!("{}", diff.unified_diff().header(&name, &name));
+        return ExitCode::FAILURE;
+    }
+    match std::fs::write(path, formatted) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let options = match parse_options(&args.options) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let root = Path::new(args.file.as_deref().unwrap_or("journal.ledger"));
+    if args.command.as_deref() == Some("fmt") {
+        return format(root, options.column, options.check);
+    }
+    let sources = match Sources::load(root) {
+        Ok(sources) => sources,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let (mut journal, diagnostics) = sources.parse_recovering();
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    let balanced = journal.balance();
+    if let Err(errors) = &balanced {
+        report(errors, |e| e.file, root);
+        // Totals that overflow cannot be reported on.
+        if errors.iter().any(|e| e.imbalance == Imbalance::Overflow) {
+            return ExitCode::FAILURE;
+        }
+    }
+    let asserted = journal.check_assertions();
+    if let Err(errors) = &asserted {
+        report(errors, |e| e.file, root);
+    }
+    let checked = match args.strict {
+        true => journal.check_strict(),
+        false => Ok(()),
+    };
+    if let Err(errors) = &checked {
+        report(errors, |e| e.file(), root);
+    }
+
+    let valuer = options.target.map(|target| {
+        let valuer = Valuer::new(&journal, target, options.valuation);
+        // Value at the end of the reported period, not of the journal.
+        match options.query.last_day() {
+            Some(last) => valuer.end(last),
+            None => valuer,
+        }
+    });
+    match args.command.as_deref() {
+        Some("balance" | "bal") => {
+            match BalanceReport::new(&journal, &options.query, &options.balance, valuer.as_ref()) {
+                Ok(report) => print!("{}", report),
+                Err(error) => {
+                    eprintln!("{}: {}", root.display(), error);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        Some("register" | "reg") => {
+            match RegisterReport::new(&journal, &options.query, &options.register, valuer.as_ref())
+            {
+                Ok(report) => print!("{}", report),
+                Err(error) => {
+                    eprintln!("{}: {}", root.display(), error);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        _ => println!("{:#?}", journal),
+    }
+    if diagnostics.is_empty() && balanced.is_ok() && asserted.is_ok() && checked.is_ok() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
 }
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+/// Tags and `key: value` pairs found in comments.
+///
+/// A comment can hold tags written as `:tag1:tag2:`, which have no value, and
+/// comma separated `key: value` pairs such as `; project: apollo, billable:`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata<'a> {
+    entries: BTreeMap<&'a str, Option<&'a str>>,
+}
+
+impl<'a> Metadata<'a> {
+    pub fn from_comments(comments: &[&'a str]) -> Self {
+        let mut metadata = Metadata::default();
+        for comment in comments {
+            metadata.parse(comment);
+        }
+        metadata
+    }
+
+    fn parse(&mut self, comment: &'a str) {
+        for part in comment.split(',') {
+            for token in part.split_whitespace() {
+                if token.len() > 2 && token.starts_with(':') && token.ends_with(':') {
+                    for tag in token.split(':').filter(|tag| !tag.is_empty()) {
+                        self.insert(tag, None);
+                    }
+                } else if let Some(key) = token
+                    .strip_suffix(':')
+                    .filter(|key| !key.is_empty() && !key.contains(':'))
+                {
+                    // The value is everything after the key up to the next
+                    // comma, so it may contain spaces.
+                    let end = token.as_ptr() as usize - part.as_ptr() as usize + token.len();
+                    let value = part[end..].trim();
+                    self.insert(key, Some(value).filter(|value| !value.is_empty()));
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: &'a str, value: Option<&'a str>) {
+        self.entries.insert(key, value);
+    }
+
+    /// Adds every entry of `other` that is not already present, so values set
+    /// here take precedence over inherited ones.
+    pub fn inherit(&mut self, other: &Metadata<'a>) {
+        for (key, value) in &other.entries {
+            self.entries.entry(key).or_insert(*value);
+        }
+    }
+
+    /// Whether `key` is present, either as a tag or with a value.
+    pub fn has(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.entries.get(key).copied().flatten()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, Option<&'a str>)> + '_ {
+        self.entries.iter().map(|(&key, &value)| (key, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_tags() {
+        let metadata = Metadata::from_comments(&[":reimbursable:work:", "see :travel: too"]);
+        assert_eq!(
+            metadata.iter().collect::<Vec<_>>(),
+            vec![("reimbursable", None), ("travel", None), ("work", None)]
+        );
+    }
+
+    #[test]
+    fn parse_key_values() {
+        let metadata = Metadata::from_comments(&[
+            "project: apollo, client: Acme Corp",
+            "billable:",
+            "see http://example.com",
+        ]);
+        assert_eq!(metadata.get("project"), Some("apollo"));
+        assert_eq!(metadata.get("client"), Some("Acme Corp"));
+        assert!(metadata.has("billable"));
+        assert_eq!(metadata.get("billable"), None);
+        assert!(!metadata.has("http"));
+    }
+
+    #[test]
+    fn inherit() {
+        let mut posting = Metadata::from_comments(&["project: gemini"]);
+        posting.inherit(&Metadata::from_comments(&[":work:", "project: apollo"]));
+        assert_eq!(posting.get("project"), Some("gemini"));
+        assert!(posting.has("work"));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{Amount, Cost, Journal};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Market prices by commodity pair and date, for converting amounts from
+/// one commodity to another.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PriceDb<'a> {
+    /// `prices[a][b][date]` is what one unit of `a` was worth in `b`.
+    prices: BTreeMap<&'a str, BTreeMap<&'a str, BTreeMap<NaiveDate, Decimal>>>,
+}
+
+impl<'a> PriceDb<'a> {
+    /// Records that one unit of `commodity` was worth `price` on `date`,
+    /// replacing any price of the same pair recorded for that day.
+    pub fn insert(&mut self, date: NaiveDate, commodity: &'a str, price: &Amount<'a>) {
+        self.prices
+            .entry(commodity)
+            .or_default()
+            .entry(price.currency)
+            .or_default()
+            .insert(date, price.amount);
+    }
+
+    /// The latest recorded price of `from` in `to` on or before `date`.
+    fn latest(&self, from: &str, to: &str, date: NaiveDate) -> Option<(NaiveDate, Decimal)> {
+        let (&day, &price) = self.prices.get(from)?.get(to)?.range(..=date).next_back()?;
+        Some((day, price))
+    }
+
+    /// The more recent of the direct price of `from` in `to` and the inverse
+    /// of the price of `to` in `from`.
+    fn quote(&self, from: &str, to: &str, date: NaiveDate) -> Option<(NaiveDate, Decimal)> {
+        let direct = self.latest(from, to, date);
+        let inverse = self
+            .latest(to, from, date)
+            .and_then(|(day, price)| Some((day, Decimal::ONE.checked_div(price)?)));
+        match (direct, inverse) {
+            (Some(direct), Some(inverse)) if inverse.0 > direct.0 => Some(inverse),
+            (Some(direct), _) => Some(direct),
+            (None, inverse) => inverse,
+        }
+    }
+
+    fn commodities(&self) -> BTreeSet<&'a str> {
+        let quoted = self.prices.values().flat_map(|prices| prices.keys());
+        self.prices.keys().chain(quoted).copied().collect()
+    }
+
+    /// How many units of `to` one unit of `from` was worth on `date`, using
+    /// the latest price on or before it. When there is no price between the
+    /// two, either way round, this goes through a third commodity priced
+    /// against both, preferring the one with the most recent prices.
+    pub fn rate(&self, from: &str, to: &str, date: NaiveDate) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+        if let Some((_, rate)) = self.quote(from, to, date) {
+            return Some(rate);
+        }
+        self.commodities()
+            .into_iter()
+            .filter(|&via| via != from && via != to)
+            .filter_map(|via| {
+                let (first_day, first) = self.quote(from, via, date)?;
+                let (second_day, second) = self.quote(via, to, date)?;
+                Some((first_day.min(second_day), first.checked_mul(second)?))
+            })
+            .max_by_key(|&(day, _)| day)
+            .map(|(_, rate)| rate)
+    }
+
+    /// `amount` expressed in `to` at the prices of `date`, or `None` if there
+    /// is no price or the result is too large to represent.
+    pub fn convert(&self, amount: &Amount, to: &'a str, date: NaiveDate) -> Option<Amount<'a>> {
+        let rate = self.rate(amount.currency, to, date)?;
+        Some(Amount::new(to, amount.amount.checked_mul(rate)?))
+    }
+}
+
+impl<'a> Journal<'a> {
+    /// The prices of the journal's `P` directives, along with those implied
+    /// by the `@` and `@@` costs of its postings. A `P` directive wins over a
+    /// cost on the same day.
+    pub fn price_db(&self) -> PriceDb<'a> {
+        let mut db = PriceDb::default();
+        for transaction in &self.transactions {
+            for posting in &transaction.postings {
+                let (Some(amount), Some(cost)) = (&posting.amount, &posting.cost) else {
+                    continue;
+                };
+                let price = match cost {
+                    Cost::Unit(price) => price.clone(),
+                    Cost::Total(total) => match total.amount.checked_div(amount.amount.abs()) {
+                        Some(price) => Amount {
+                            amount: price,
+                            ..total.clone()
+                        },
+                        // A zero or tiny amount implies no usable price.
+                        None => continue,
+                    },
+                };
+                db.insert(posting.effective_date(transaction), amount.currency, &price);
+            }
+        }
+        for price in &self.prices {
+            db.insert(price.date, price.commodity, &price.price);
+        }
+        db
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn look_up_prices() {
+        let journal = parse("P 2024-03-01 AAPL 170 USD\nP 2024-03-05 AAPL 180 USD\nP 2024-03-01 EUR 1.25 USD\nP 2024-03-01 GBP 1.25 EUR\n").unwrap();
+        let db = journal.price_db();
+        assert_eq!(db.rate("AAPL", "USD", day(4)), Some(Decimal::new(170, 0)));
+        assert_eq!(db.rate("AAPL", "USD", day(5)), Some(Decimal::new(180, 0)));
+        assert_eq!(db.rate("AAPL", "USD", day(1) - chrono::Days::new(1)), None);
+        assert_eq!(db.rate("USD", "EUR", day(2)), Some(Decimal::new(8, 1)));
+        assert_eq!(db.rate("AAPL", "EUR", day(5)), Some(Decimal::new(144, 0)));
+        assert_eq!(db.rate("GBP", "USD", day(5)), Some(Decimal::new(15625, 4)));
+        assert_eq!(db.rate("GBP", "AAPL", day(5)), None);
+        assert_eq!(db.rate("USD", "USD", day(5)), Some(Decimal::ONE));
+    }
+
+    #[test]
+    fn prices_implied_by_costs() {
+        let journal = parse("2024-03-01 Buy\n\tAssets:Broker  10 AAPL @ 150 USD\n\tAssets:Cash\n\n2024-03-02 Sell\n\tAssets:Broker  -4 AAPL @@ 640 USD\n\tAssets:Cash\n\nP 2024-03-02 AAPL 161 USD\n").unwrap();
+        let db = journal.price_db();
+        assert_eq!(db.rate("AAPL", "USD", day(1)), Some(Decimal::new(150, 0)));
+        assert_eq!(db.rate("AAPL", "USD", day(2)), Some(Decimal::new(161, 0)));
+        let amount = journal.transactions[1].postings[0].amount.as_ref().unwrap();
+        assert_eq!(
+            db.convert(amount, "USD", day(1)).unwrap().amount,
+            Decimal::new(-600, 0)
+        );
+    }
+
+    #[test]
+    fn overflowing_conversions() {
+        let journal =
+            parse("P 2024-03-01 AAPL 79228162514264337593543950335 EUR\nP 2024-03-01 EUR 2 USD\n")
+                .unwrap();
+        let db = journal.price_db();
+        assert_eq!(db.rate("AAPL", "USD", day(1)), None);
+        let huge = crate::Amount::new("EUR", Decimal::MAX);
+        assert_eq!(db.convert(&huge, "USD", day(1)), None);
+    }
+}
@@ -0,0 +1,447 @@
+use crate::{
+    Amount, AmountStyle, BalanceAssertion, Cost, Lot, Posting, PostingKind, Transaction,
+    TransactionState,
+};
+use std::fmt::{self, Write};
+
+/// A `;` comment, without a space after the `;` if it is empty.
+fn comment(text: &str) -> String {
+    match text.is_empty() {
+        true => ";".to_string(),
+        false => format!("; {}", text),
+    }
+}
+
+fn marker(state: TransactionState) -> Option<&'static str> {
+    match state {
+        TransactionState::Cleared => Some("*"),
+        TransactionState::Pending => Some("!"),
+        TransactionState::Uncleared => None,
+    }
+}
+
+impl fmt::Display for Amount<'_> {
+    /// Writes the amount in the style it was written in, e.g. `USD 1,000.00`
+    /// or `12,5 EUR`. Digits beyond the style's precision are kept, so an
+    /// amount always reads back as the same value.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let style = AmountStyle {
+            precision: self.style.precision.max(self.amount.scale()),
+            ..self.style
+        };
+        f.pad(&style.format(self.currency, self.amount))
+    }
+}
+
+impl fmt::Display for Cost<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cost::Unit(price) => write!(f, "@ {}", price),
+            Cost::Total(price) => write!(f, "@@ {}", price),
+        }
+    }
+}
+
+impl fmt::Display for Lot<'_> {
+    /// Writes the lot's annotations as `{150 USD} [2024-01-03] (lot-a)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut annotations = Vec::new();
+        match &self.cost {
+            Some(Cost::Unit(price)) => annotations.push(format!("{{{}}}", price)),
+            Some(Cost::Total(price)) => annotations.push(format!("{{{{{}}}}}", price)),
+            None => {}
+        }
+        annotations.extend(self.date.map(|date| format!("[{}]", date)));
+        annotations.extend(self.note.map(|note| format!("({})", note)));
+        write!(f, "{}", annotations.join(" "))
+    }
+}
+
+impl fmt::Display for BalanceAssertion<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = if self.total { "==" } else { "=" };
+        let inclusive = if self.inclusive { "*" } else { "" };
+        write!(f, "{}{} {}", total, inclusive, self.amount)
+    }
+}
+
+impl Posting<'_> {
+    /// The posting's marker and account, bracketed if it is virtual.
+    pub(crate) fn account_text(&self) -> String {
+        let state = marker(self.state).map_or(String::new(), |state| format!("{} ", state));
+        match self.kind {
+            PostingKind::Real => format!("{}{}", state, self.account.name),
+            PostingKind::Virtual => format!("{}({})", state, self.account.name),
+            PostingKind::BalancedVirtual => format!("{}[{}]", state, self.account.name),
+        }
+    }
+
+    /// Everything written after the amount: the lot, cost, assertion and
+    /// comments. Comments go on lines of their own, except for the first if
+    /// it was written at the end of the posting's line.
+    pub(crate) fn trailer(&self) -> String {
+        let mut trailer = String::new();
+        if let Some(lot) = &self.lot {
+            trailer += &format!(" {}", lot);
+        }
+        if let Some(cost) = &self.cost {
+            trailer += &format!(" {}", cost);
+        }
+        if let Some(assertion) = &self.assertion {
+            let space = if self.amount.is_some() { " " } else { "  " };
+            trailer += &format!("{}{}", space, assertion);
+        }
+        for (i, text) in self.comments.iter().enumerate() {
+            let space = if i == 0 && self.trailing_comment {
+                "  "
+            } else {
+                "\n\t"
+            };
+            trailer += &format!("{}{}", space, comment(text));
+        }
+        trailer
+    }
+
+    /// Writes the posting with its account padded to `account_width` and its
+    /// amount right-aligned to `amount_width`.
+    fn write(&self, f: &mut impl Write, account_width: usize, amount_width: usize) -> fmt::Result {
+        let account = self.account_text();
+        match &self.amount {
+            Some(amount) => write!(f, "{:account_width$}  {:>amount_width$}", account, amount)?,
+            None => write!(f, "{}", account)?,
+        }
+        write!(f, "{}", self.trailer())
+    }
+}
+
+impl fmt::Display for Posting<'_> {
+    /// Writes the posting as it would appear in a transaction, without the
+    /// leading tab. Comments other than a trailing one go on lines of their
+    /// own.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0, 0)
+    }
+}
+
+impl Transaction<'_> {
+    /// Writes the transaction with each amount ending `column` characters
+    /// after the posting's indentation, or two spaces after the account where
+    /// it is too long for that. Otherwise the same as its [`fmt::Display`].
+    pub fn to_string_aligned(&self, column: usize) -> String {
+        let mut text = String::new();
+        self.write(&mut text, Some(column))
+            .expect("writing to a string cannot fail");
+        text
+    }
+
+    fn write(&self, f: &mut impl Write, column: Option<usize>) -> fmt::Result {
+        let mut date = self.date.to_string();
+        if let Some(auxillary_date) = self.auxillary_date {
+            write!(date, "={}", auxillary_date)?;
+        }
+        let description = match self.merchant {
+            Some(merchant) => format!("{} | {}", merchant, self.memo),
+            None => self.memo.to_string(),
+        };
+        let header = [Some(date), marker(self.state).map(str::to_string)]
+            .into_iter()
+            .chain([self.code.map(|code| format!("({})", code))])
+            .chain([Some(description).filter(|text| !text.is_empty())])
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut comments = self.comments.iter().peekable();
+        match comments.peek().filter(|_| self.trailing_comment) {
+            Some(text) => {
+                write!(f, "{}  {}", header, comment(text))?;
+                comments.next();
+            }
+            None => write!(f, "{}", header)?,
+        }
+        for text in comments {
+            write!(f, "\n\t{}", comment(text))?;
+        }
+
+        let with_amounts = self.postings.iter().filter(|p| p.amount.is_some());
+        let account_width = with_amounts
+            .clone()
+            .map(|posting| posting.account_text().chars().count())
+            .max()
+            .unwrap_or(0);
+        let amount_width = with_amounts
+            .filter_map(|posting| posting.amount.as_ref())
+            .map(|amount| amount.to_string().chars().count())
+            .max()
+            .unwrap_or(0);
+        for posting in &self.postings {
+            write!(f, "\n\t")?;
+            match (column, &posting.amount) {
+                (Some(column), Some(amount)) => {
+                    let width = amount.to_string().chars().count();
+                    posting.write(f, column.saturating_sub(width + 2), 0)?
+                }
+                _ => posting.write(f, account_width, amount_width)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Transaction<'_> {
+    /// Writes the transaction as journal text: the header with ISO dates,
+    /// then its comments and its postings, tab indented, with accounts padded
+    /// and amounts right-aligned into columns. There is no final newline.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{transaction, AmountStyle, Side, Transaction};
+    use proptest::prelude::*;
+    use rust_decimal::Decimal;
+
+    fn parse(text: &str) -> Transaction<'_> {
+        let (rest, transaction) = transaction(text).unwrap();
+        assert!(rest.trim().is_empty(), "left over: {:?}", rest);
+        transaction
+    }
+
+    #[test]
+    fn print_transaction() {
+        let text = "2024/3/1=2024/3/2 * (42) Broker | Buy shares ; :trade:
+\t; note: first
+\tAssets:Broker  10 AAPL {150.00 USD} [2024-01-03] @ 151 USD ; [2024-03-04]
+\t! [Budget:Savings]   USD -1,510
+\t(Tracking)  = 10 AAPL
+\tAssets:Cash";
+        assert_eq!(
+            parse(text).to_string(),
+            "2024-03-01=2024-03-02 * (42) Broker | Buy shares  ; :trade:
+\t; note: first
+\tAssets:Broker          10 AAPL {150.00 USD} [2024-01-03] @ 151 USD  ; [2024-03-04]
+\t! [Budget:Savings]  USD -1,510
+\t(Tracking)  = 10 AAPL
+\tAssets:Cash"
+        );
+        assert_eq!(
+            parse("2024-03-01 \n\tA  $1").to_string(),
+            "2024-03-01\n\tA  $1"
+        );
+    }
+
+    #[test]
+    fn align_amounts_to_column() {
+        let transaction =
+            parse("2024-03-01 Shop\n\tExpenses:Food  USD 1.25\n\tAssets:Cash  $-1\n\tEquity");
+        assert_eq!(
+            transaction.to_string_aligned(24),
+            "2024-03-01 Shop\n\tExpenses:Food   USD 1.25\n\tAssets:Cash          $-1\n\tEquity"
+        );
+        assert_eq!(
+            transaction.to_string_aligned(10),
+            "2024-03-01 Shop\n\tExpenses:Food  USD 1.25\n\tAssets:Cash  $-1\n\tEquity"
+        );
+    }
+
+    #[test]
+    fn keep_comment_layout() {
+        let text = "2024-03-01 Shop
+\t; receipt: 42
+\tExpenses:Food  USD 5
+\t; lunch
+\tAssets:Cash  ; wallet
+\t; coins";
+        assert_eq!(parse(text).to_string(), text);
+    }
+
+    #[test]
+    fn no_trailing_whitespace() {
+        let text = "2024-03-01  ;
+\t;
+\tExpenses:Food  USD 5  ;
+\tAssets:Cash
+\t;";
+        assert_eq!(parse(text).to_string(), text);
+        assert_eq!(
+            parse("2024-03-01\n\tAssets").to_string(),
+            "2024-03-01\n\tAssets"
+        );
+    }
+
+    #[test]
+    fn print_inferred_amounts() {
+        let mut transaction = parse("2024-03-01 Shop\n\tExpenses  USD 1.255\n\tAssets:Cash");
+        transaction.balance().unwrap();
+        assert_eq!(
+            transaction.to_string(),
+            "2024-03-01 Shop\n\tExpenses      USD 1.255\n\tAssets:Cash  USD -1.255"
+        );
+        let mut transaction = parse("2024-03-01 Shop\n\tExpenses  20.00 EUR\n\tAssets:Cash");
+        transaction.balance().unwrap();
+        assert_eq!(
+            transaction.to_string(),
+            "2024-03-01 Shop\n\tExpenses      20.00 EUR\n\tAssets:Cash  -20.00 EUR"
+        );
+        let mut transaction = parse("2024-03-01 Shop\n\tExpenses  $20\n\tAssets:Cash");
+        transaction.balance().unwrap();
+        assert_eq!(
+            transaction.to_string(),
+            "2024-03-01 Shop\n\tExpenses      $20\n\tAssets:Cash  $-20"
+        );
+    }
+
+    fn amount() -> impl Strategy<Value = String> {
+        let style = prop_oneof![
+            (0..4u32).prop_map(|precision| ('.', None, precision)),
+            (0..4u32).prop_map(|precision| ('.', Some(','), precision)),
+            (1..3u32).prop_map(|precision| (',', Some('.'), precision)),
+        ];
+        let commodity = prop::sample::select(vec!["USD", "$", "€", "AAPL", "BRK.B", "Air Miles"]);
+        (
+            style,
+            commodity,
+            -10_000_000..10_000_000i64,
+            any::<(bool, bool)>(),
+        )
+            .prop_map(
+                |(
+                    (decimal_mark, digit_group_mark, precision),
+                    commodity,
+                    units,
+                    (left, spaced),
+                )| {
+                    let style = AmountStyle {
+                        commodity_side: if left { Side::Left } else { Side::Right },
+                        commodity_spaced: spaced,
+                        decimal_mark,
+                        digit_group_mark,
+                        precision,
+                    };
+                    style.format(commodity, Decimal::new(units, precision))
+                },
+            )
+    }
+
+    fn comment() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[a-z]{1,6}( [a-z]{1,6})?",
+            "[a-z]{1,6}: [a-z]{1,6}",
+            ":[a-z]{1,6}:",
+            Just("[2024-03-05=2024-03-06]".to_string()),
+            Just("date: 2024-03-07".to_string()),
+        ]
+    }
+
+    fn posting() -> impl Strategy<Value = String> {
+        let state = prop::sample::select(vec!["", "* ", "! "]);
+        let account = "[A-Z][a-z]{1,6}( [a-z]{1,4})?(:[A-Z][a-z]{1,6}){0,2}";
+        let kind = prop::sample::select(vec![("", ""), ("(", ")"), ("[", "]")]);
+        let lot = (
+            prop::option::of((amount(), any::<bool>())),
+            prop::option::of(Just("[2024-01-03]")),
+            prop::option::of("[a-z]{1,6}"),
+        )
+            .prop_map(|(cost, date, note)| {
+                let mut lot = String::new();
+                match cost {
+                    Some((price, true)) => lot += &format!(" {{{{{}}}}}", price),
+                    Some((price, false)) => lot += &format!(" {{{}}}", price),
+                    None => {}
+                }
+                lot.extend(date.map(|date| format!(" {}", date)));
+                lot.extend(note.map(|note| format!(" ({})", note)));
+                lot
+            });
+        let cost =
+            prop::option::of((prop::sample::select(vec!["@", "@@"]), amount())).prop_map(|cost| {
+                cost.map_or(String::new(), |(at, price)| format!(" {} {}", at, price))
+            });
+        let priced = prop::option::of((amount(), lot, cost, " {2,6}")).prop_map(|amount| {
+            amount.map_or(String::new(), |(amount, lot, cost, space)| {
+                format!("{}{}{}{}", space, amount, lot, cost)
+            })
+        });
+        let assertion = prop::option::of((prop::sample::select(vec!["=", "==", "=*"]), amount()))
+            .prop_map(|assertion| {
+                assertion.map_or(String::new(), |(op, amount)| format!("  {} {}", op, amount))
+            });
+        let comments = (prop::collection::vec(comment(), 0..3), any::<bool>());
+        (state, kind, account, priced, assertion, comments).prop_map(
+            |(state, (open, close), account, amount, assertion, (comments, trailing))| {
+                let mut posting = format!(
+                    "\t{}{}{}{}{}{}",
+                    state, open, account, close, amount, assertion
+                );
+                for (i, comment) in comments.iter().enumerate() {
+                    match i == 0 && trailing {
+                        true => posting += &format!("  ; {}", comment),
+                        false => posting += &format!("\n\t; {}", comment),
+                    }
+                }
+                posting
+            },
+        )
+    }
+
+    fn transaction_text() -> impl Strategy<Value = String> {
+        let date = (
+            2000..2030i32,
+            1..13u32,
+            1..29u32,
+            prop::sample::select(vec!["-", "/"]),
+        )
+            .prop_map(|(year, month, day, separator)| {
+                format!("{}{sep}{:02}{sep}{:02}", year, month, day, sep = separator)
+            });
+        let header = (
+            date.clone(),
+            prop::option::of(date),
+            prop::sample::select(vec!["", "* ", "! "]),
+            prop::option::of("[a-z0-9]{1,4}"),
+            prop::option::of("[A-Z][a-z]{1,8}( [A-Z][a-z]{1,8})?"),
+            "[A-Z][a-z]{1,8}( [a-z]{1,8}){0,2}",
+            (prop::collection::vec(comment(), 0..3), any::<bool>()),
+        )
+            .prop_map(
+                |(date, auxillary_date, state, code, merchant, memo, (comments, trailing))| {
+                    let mut header = date;
+                    header.extend(auxillary_date.map(|date| format!("={}", date)));
+                    header += &format!(" {}", state);
+                    header.extend(code.map(|code| format!("({}) ", code)));
+                    header.extend(merchant.map(|merchant| format!("{} | ", merchant)));
+                    header += &memo;
+                    for (i, comment) in comments.iter().enumerate() {
+                        match i == 0 && trailing {
+                            true => header += &format!(" ; {}", comment),
+                            false => header += &format!("\n\t; {}", comment),
+                        }
+                    }
+                    header
+                },
+            );
+        (header, prop::collection::vec(posting(), 1..5))
+            .prop_map(|(header, postings)| format!("{}\n{}", header, postings.join("\n")))
+    }
+
+    /// Posting lines depend on where comments were written, so they are left
+    /// out of the comparison.
+    fn without_lines(mut transaction: Transaction) -> Transaction {
+        for posting in &mut transaction.postings {
+            posting.line = 0;
+        }
+        transaction
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(text in transaction_text()) {
+            let parsed = parse(&text);
+            let printed = parsed.to_string();
+            let reparsed = parse(&printed);
+            prop_assert_eq!(reparsed.to_string(), printed.clone());
+            prop_assert_eq!(without_lines(reparsed), without_lines(parsed));
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{Journal, Posting, Transaction, TransactionState};
+use chrono::NaiveDate;
+
+/// Selects postings from a journal. An empty query matches every posting;
+/// each added condition narrows it further.
+#[derive(Debug, Clone, Default)]
+pub struct Query<'q> {
+    tags: Vec<(&'q str, Option<&'q str>)>,
+    real: bool,
+    state: Option<TransactionState>,
+    begin: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+}
+
+impl<'q> Query<'q> {
+    pub fn new() -> Self {
+        Query::default()
+    }
+
+    /// Only match postings carrying `tag`, with or without a value.
+    pub fn tag(mut self, tag: &'q str) -> Self {
+        self.tags.push((tag, None));
+        self
+    }
+
+    /// Only match postings where `key` is set to `value`.
+    pub fn tag_value(mut self, key: &'q str, value: &'q str) -> Self {
+        self.tags.push((key, Some(value)));
+        self
+    }
+
+    /// Leave out virtual postings, i.e. `(Account)` and `[Account]`.
+    pub fn real(mut self) -> Self {
+        self.real = true;
+        self
+    }
+
+    /// Only match postings with this status, taking it from the transaction
+    /// when the posting has no marker of its own.
+    pub fn state(mut self, state: TransactionState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Only match postings dated on or after `date`.
+    pub fn begin(mut self, date: NaiveDate) -> Self {
+        self.begin = Some(date);
+        self
+    }
+
+    /// Only match postings dated before `date`.
+    pub fn end(mut self, date: NaiveDate) -> Self {
+        self.end = Some(date);
+        self
+    }
+
+    /// The last day the query matches, if it has an end date.
+    pub fn last_day(&self) -> Option<NaiveDate> {
+        self.end.and_then(|end| end.pred_opt())
+    }
+
+    pub fn matches(&self, transaction: &Transaction, posting: &Posting) -> bool {
+        if self.real && posting.is_virtual() {
+            return false;
+        }
+        if self
+            .state
+            .is_some_and(|state| posting.effective_state(transaction) != state)
+        {
+            return false;
+        }
+        let date = posting.effective_date(transaction);
+        if self.begin.is_some_and(|begin| date < begin) || self.end.is_some_and(|end| date >= end) {
+            return false;
+        }
+        self.tags.iter().all(|&(key, value)| match value {
+            Some(value) => posting.metadata.get(key) == Some(value),
+            None => posting.metadata.has(key),
+        })
+    }
+}
+
+impl<'a> Journal<'a> {
+    /// Every posting matching `query`, with the transaction it belongs to, in
+    /// journal order.
+    pub fn postings<'j>(
+        &'j self,
+        query: &'j Query,
+    ) -> impl Iterator<Item = (&'j Transaction<'a>, &'j Posting<'a>)> + 'j {
+        self.transactions.iter().flat_map(move |transaction| {
+            transaction
+                .postings
+                .iter()
+                .filter(move |posting| query.matches(transaction, posting))
+                .map(move |posting| (transaction, posting))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn query_by_tags() {
+        let journal = parse("2024-03-01 Flight ; :reimbursable:\n\tExpenses:Travel  USD300 ; project: apollo\n\tExpenses:Travel  USD20 ; project: gemini\n\tLiabilities:Credit\n\n2024-03-02 Lunch\n\tExpenses:Food  USD20\n\tLiabilities:Credit\n").unwrap();
+        let accounts = |query: Query| {
+            journal
+                .postings(&query)
+                .map(|(t, p)| (t.memo, p.account.name))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            accounts(Query::new().tag("reimbursable")),
+            vec![
+                ("Flight", "Expenses:Travel"),
+                ("Flight", "Expenses:Travel"),
+                ("Flight", "Liabilities:Credit")
+            ]
+        );
+        assert_eq!(
+            accounts(
+                Query::new()
+                    .tag("reimbursable")
+                    .tag_value("project", "apollo")
+            ),
+            vec![("Flight", "Expenses:Travel")]
+        );
+        assert_eq!(accounts(Query::new()).len(), 5);
+    }
+
+    #[test]
+    fn query_real_postings() {
+        let journal = parse("2024-03-01 Shop\n\tExpenses:Food  USD 20\n\tAssets:Checking\n\t(Budget:Food)  USD -20\n\t[Envelopes:Food]  USD -20\n\t[Envelopes:Available]\n").unwrap();
+        let query = Query::new().real();
+        let accounts: Vec<_> = journal
+            .postings(&query)
+            .map(|(_, p)| p.account.name)
+            .collect();
+        assert_eq!(accounts, vec!["Expenses:Food", "Assets:Checking"]);
+    }
+
+    #[test]
+    fn query_by_state_and_date() {
+        let journal = parse("2024-03-01 * Shop\n\tExpenses:Food  USD 20\n\t! Liabilities:Credit ; [2024-03-05]\n\n2024-03-02 Rent\n\tExpenses:Rent  USD 500\n\t* Assets:Checking\n").unwrap();
+        let accounts = |query: Query| {
+            journal
+                .postings(&query)
+                .map(|(_, p)| p.account.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            accounts(Query::new().state(TransactionState::Cleared)),
+            vec!["Expenses:Food", "Assets:Checking"]
+        );
+        assert_eq!(
+            accounts(Query::new().state(TransactionState::Uncleared)),
+            vec!["Expenses:Rent"]
+        );
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        assert_eq!(
+            accounts(Query::new().begin(day(2)).end(day(5))),
+            vec!["Expenses:Rent", "Assets:Checking"]
+        );
+        assert_eq!(
+            accounts(Query::new().begin(day(5))),
+            vec!["Liabilities:Credit"]
+        );
+    }
+}
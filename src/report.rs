@@ -0,0 +1,30 @@
+use crate::{Amount, AmountStyle, Balance};
+use std::collections::BTreeMap;
+
+mod balance;
+mod register;
+
+pub use balance::{BalanceOptions, BalanceReport, BalanceRow};
+pub use register::{RegisterOptions, RegisterReport, RegisterRow};
+
+/// Writes `amount` in its commodity's display style, or as it was written
+/// if the commodity has none.
+fn format_amount(styles: &BTreeMap<&str, AmountStyle>, amount: &Amount) -> String {
+    let style = styles.get(amount.currency).copied().unwrap_or(AmountStyle {
+        precision: amount.amount.scale(),
+        ..amount.style
+    });
+    style.format(amount.currency, amount.amount)
+}
+
+/// The lines of a balance, one per commodity, or a single `0`.
+fn format_balance(styles: &BTreeMap<&str, AmountStyle>, balance: &Balance) -> Vec<String> {
+    let lines: Vec<String> = balance
+        .amounts()
+        .map(|amount| format_amount(styles, &amount))
+        .collect();
+    match lines.is_empty() {
+        true => vec!["0".to_string()],
+        false => lines,
+    }
+}
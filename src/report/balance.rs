@@ -0,0 +1,326 @@
+use super::format_balance;
+use crate::{AmountStyle, Balance, Journal, Overflow, Query, Valuer};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How to lay out a [`BalanceReport`].
+#[derive(Debug, Clone, Default)]
+pub struct BalanceOptions {
+    depth: Option<usize>,
+    flat: bool,
+    empty: bool,
+}
+
+impl BalanceOptions {
+    pub fn new() -> Self {
+        BalanceOptions::default()
+    }
+
+    /// Only show accounts this many levels deep, adding the balances of
+    /// deeper accounts to their ancestor at that level.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// List accounts by their full names, each with only its own balance,
+    /// instead of as a tree of totals.
+    pub fn flat(mut self) -> Self {
+        self.flat = true;
+        self
+    }
+
+    /// Also show accounts whose balance is zero.
+    pub fn empty(mut self) -> Self {
+        self.empty = true;
+        self
+    }
+}
+
+/// One line of a [`BalanceReport`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceRow<'a> {
+    /// The account's full name.
+    pub account: &'a str,
+    /// How far to indent the account: its depth in the tree, or 0 when flat.
+    pub indent: usize,
+    /// The name to show: the last part of the account name in a tree, or
+    /// all of it when flat.
+    pub name: &'a str,
+    /// In a tree, the total of the account and all of its subaccounts.
+    pub balance: Balance<'a>,
+}
+
+/// The balances of accounts, like `ledger balance`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceReport<'a> {
+    pub rows: Vec<BalanceRow<'a>>,
+    /// The total of every matching posting, per commodity.
+    pub total: Balance<'a>,
+    styles: BTreeMap<&'a str, AmountStyle>,
+}
+
+/// The first `depth` parts of `account`.
+fn clip(account: &str, depth: Option<usize>) -> &str {
+    match depth.and_then(|depth| account.match_indices(':').nth(depth.max(1) - 1)) {
+        Some((end, _)) => &account[..end],
+        None => account,
+    }
+}
+
+impl<'a> BalanceReport<'a> {
+    /// Sums the postings of `journal` matching `query` by account, valued by
+    /// `valuer` if given. Fails if a balance gets too large to represent.
+    pub fn new(
+        journal: &Journal<'a>,
+        query: &Query,
+        options: &BalanceOptions,
+        valuer: Option<&Valuer<'a>>,
+    ) -> Result<Self, Overflow> {
+        // Own balances, keyed by the parts of the account name so that
+        // subaccounts sort directly after their parent.
+        let mut own: BTreeMap<Vec<&'a str>, (&'a str, Balance<'a>)> = BTreeMap::new();
+        let mut total = Balance::default();
+        for (transaction, posting) in journal.postings(query) {
+            let amount = match valuer {
+                Some(valuer) => valuer.posting(transaction, posting)?,
+                None => posting.amount.clone(),
+            };
+            let Some(amount) = amount else {
+                continue;
+            };
+            let account = clip(posting.account.name, options.depth);
+            let parts = account.split(':').collect();
+            own.entry(parts)
+                .or_insert_with(|| (account, Balance::default()))
+                .1
+                .checked_add(&amount)
+                .ok_or(Overflow)?;
+            total.checked_add(&amount).ok_or(Overflow)?;
+        }
+
+        let rows = match options.flat {
+            true => own
+                .into_values()
+                .map(|(account, balance)| BalanceRow {
+                    account,
+                    indent: 0,
+                    name: account,
+                    balance,
+                })
+                .collect(),
+            false => tree(own)?,
+        };
+        let rows = rows
+            .iter()
+            .filter(|row| {
+                // A parent stays to hold its subaccounts even if it nets to
+                // zero.
+                options.empty
+                    || rows.iter().any(|other| {
+                        !other.balance.is_zero()
+                            && (other.account == row.account
+                                || other
+                                    .account
+                                    .strip_prefix(row.account)
+                                    .is_some_and(|sub| !options.flat && sub.starts_with(':')))
+                    })
+            })
+            .cloned()
+            .collect();
+        Ok(BalanceReport {
+            rows,
+            total,
+            styles: journal.commodity_styles(),
+        })
+    }
+}
+
+/// Rolls own balances up into every ancestor account.
+fn tree<'a>(
+    own: BTreeMap<Vec<&'a str>, (&'a str, Balance<'a>)>,
+) -> Result<Vec<BalanceRow<'a>>, Overflow> {
+    let mut totals: BTreeMap<Vec<&'a str>, (&'a str, Balance<'a>)> = BTreeMap::new();
+    for (parts, (account, balance)) in own {
+        for depth in 1..=parts.len() {
+            let name = clip(account, Some(depth));
+            let total = &mut totals
+                .entry(parts[..depth].to_vec())
+                .or_insert_with(|| (name, Balance::default()))
+                .1;
+            for amount in balance.amounts() {
+                total.checked_add(&amount).ok_or(Overflow)?;
+            }
+        }
+    }
+    Ok(totals
+        .into_iter()
+        .map(|(parts, (account, balance))| BalanceRow {
+            account,
+            indent: parts.len() - 1,
+            name: parts[parts.len() - 1],
+            balance,
+        })
+        .collect())
+}
+
+impl fmt::Display for BalanceReport<'_> {
+    /// Right-aligns each balance, one commodity per line, with the account
+    /// on its last line, and ends with the total below a rule.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows: Vec<(Vec<String>, String)> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let name = format!("{:1$}{2}", "", row.indent * 2, row.name);
+                (format_balance(&self.styles, &row.balance), name)
+            })
+            .collect();
+        let total = format_balance(&self.styles, &self.total);
+        let width = rows
+            .iter()
+            .flat_map(|(lines, _)| lines)
+            .chain(&total)
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(20);
+        for (lines, name) in &rows {
+            for (i, line) in lines.iter().enumerate() {
+                match i + 1 == lines.len() {
+                    true => writeln!(f, "{:>width$}  {}", line, name)?,
+                    false => writeln!(f, "{:>width$}", line)?,
+                }
+            }
+        }
+        writeln!(f, "{}", "-".repeat(width))?;
+        for line in &total {
+            writeln!(f, "{:>width$}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse, Valuation};
+    use rust_decimal::Decimal;
+
+    const JOURNAL: &str = "2024-03-01 Opening
+\tAssets:Bank:Checking  USD 1,000.00
+\tAssets:Bank:Savings  USD 500
+\tAssets:Cash  EUR 20
+\tEquity:Opening  EUR -20
+\tEquity:Opening
+
+2024-03-02 Groceries
+\tExpenses:Food  USD 40
+\tAssets:Bank:Checking
+
+2024-03-03 Move
+\tAssets:Cash  EUR -20
+\tAssets:Wallet  EUR 20
+";
+
+    fn report(options: BalanceOptions) -> String {
+        let mut journal = parse(JOURNAL).unwrap();
+        journal.balance().unwrap();
+        BalanceReport::new(&journal, &Query::new(), &options, None)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn balance_tree() {
+        assert_eq!(
+            report(BalanceOptions::new()),
+            "              EUR 20
+        USD 1,460.00  Assets
+        USD 1,460.00    Bank
+          USD 960.00      Checking
+          USD 500.00      Savings
+              EUR 20    Wallet
+             EUR -20
+       USD -1,500.00  Equity
+             EUR -20
+       USD -1,500.00    Opening
+           USD 40.00  Expenses
+           USD 40.00    Food
+--------------------
+                   0
+"
+        );
+        assert!(report(BalanceOptions::new().empty()).contains("\n                   0    Cash\n"));
+    }
+
+    #[test]
+    fn limit_depth() {
+        assert_eq!(
+            report(BalanceOptions::new().depth(1)),
+            "              EUR 20
+        USD 1,460.00  Assets
+             EUR -20
+       USD -1,500.00  Equity
+           USD 40.00  Expenses
+--------------------
+                   0
+"
+        );
+        assert_eq!(
+            report(BalanceOptions::new().flat().depth(2)),
+            "        USD 1,460.00  Assets:Bank
+              EUR 20  Assets:Wallet
+             EUR -20
+       USD -1,500.00  Equity:Opening
+           USD 40.00  Expenses:Food
+--------------------
+                   0
+"
+        );
+    }
+
+    #[test]
+    fn value_balances() {
+        let source = format!("{}\nP 2024-03-01 EUR 1.5 USD\n", JOURNAL);
+        let mut journal = parse(&source).unwrap();
+        journal.balance().unwrap();
+        let valuer = Valuer::new(&journal, "USD", Valuation::End);
+        let query = Query::new();
+        let report = BalanceReport::new(
+            &journal,
+            &query,
+            &BalanceOptions::new().depth(1),
+            Some(&valuer),
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .rows
+                .iter()
+                .map(|row| (row.account, row.balance.get("USD")))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Assets", Decimal::new(1490, 0)),
+                ("Equity", Decimal::new(-1530, 0)),
+                ("Expenses", Decimal::new(40, 0)),
+            ]
+        );
+        assert!(report.total.is_zero());
+    }
+
+    #[test]
+    fn reject_overflowing_totals() {
+        let half = "USD 50000000000000000000000000000";
+        let source = format!("2024-03-01 Deposit\n\tAssets:A  {half}\n\tIncome:A\n\n2024-03-02 Deposit\n\tAssets:B  {half}\n\tIncome:B\n");
+        let mut journal = parse(&source).unwrap();
+        journal.balance().unwrap();
+        let query = Query::new();
+        let options = BalanceOptions::new();
+        assert_eq!(
+            BalanceReport::new(&journal, &query, &options, None),
+            Err(Overflow)
+        );
+        assert!(BalanceReport::new(&journal, &query, &options.flat(), None).is_ok());
+    }
+}
@@ -0,0 +1,271 @@
+use super::format_balance;
+use crate::{AmountStyle, Balance, Journal, Overflow, Query, Valuer};
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How to lay out a [`RegisterReport`].
+#[derive(Debug, Clone, Default)]
+pub struct RegisterOptions {
+    auxillary_date: bool,
+    sort_by_date: bool,
+    collapse: bool,
+}
+
+impl RegisterOptions {
+    pub fn new() -> Self {
+        RegisterOptions::default()
+    }
+
+    /// Show and sort by each posting's auxillary date, where it has one.
+    pub fn auxillary_date(mut self) -> Self {
+        self.auxillary_date = true;
+        self
+    }
+
+    /// List postings in date order rather than in journal order.
+    pub fn sort_by_date(mut self) -> Self {
+        self.sort_by_date = true;
+        self
+    }
+
+    /// Show one line per transaction, summing its matching postings.
+    pub fn collapse(mut self) -> Self {
+        self.collapse = true;
+        self
+    }
+}
+
+/// One line of a [`RegisterReport`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterRow<'a> {
+    pub date: NaiveDate,
+    /// The transaction's merchant, or its memo if it has none.
+    pub payee: &'a str,
+    /// The posting's account, or `<Total>` for collapsed postings to several
+    /// accounts.
+    pub account: &'a str,
+    pub amount: Balance<'a>,
+    /// The sum of this line's amount and those of every line before it.
+    pub total: Balance<'a>,
+}
+
+/// Postings with a running total, like `ledger register`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterReport<'a> {
+    pub rows: Vec<RegisterRow<'a>>,
+    styles: BTreeMap<&'a str, AmountStyle>,
+}
+
+impl<'a> RegisterReport<'a> {
+    /// Lists the postings of `journal` matching `query`, valued by `valuer`
+    /// if given. Fails if a total gets too large to represent.
+    pub fn new(
+        journal: &Journal<'a>,
+        query: &Query,
+        options: &RegisterOptions,
+        valuer: Option<&Valuer<'a>>,
+    ) -> Result<Self, Overflow> {
+        let mut rows: Vec<RegisterRow<'a>> = Vec::new();
+        let mut previous = None;
+        for (transaction, posting) in journal.postings(query) {
+            let amount = match valuer {
+                Some(valuer) => valuer.posting(transaction, posting)?,
+                None => posting.amount.clone(),
+            };
+            let date = match options.auxillary_date {
+                true => posting.effective_auxillary_date(transaction),
+                false => None,
+            };
+            let date = date.unwrap_or(posting.effective_date(transaction));
+            let same_transaction = previous.is_some_and(|p| std::ptr::eq(p, transaction));
+            previous = Some(transaction);
+            let row = match rows.last_mut() {
+                Some(row) if options.collapse && same_transaction => {
+                    if row.account != posting.account.name {
+                        row.account = "<Total>";
+                    }
+                    row
+                }
+                _ => {
+                    rows.push(RegisterRow {
+                        date,
+                        payee: transaction.merchant.unwrap_or(transaction.memo),
+                        account: posting.account.name,
+                        amount: Balance::default(),
+                        total: Balance::default(),
+                    });
+                    rows.last_mut().unwrap()
+                }
+            };
+            if let Some(amount) = amount {
+                row.amount.checked_add(&amount).ok_or(Overflow)?;
+            }
+        }
+        if options.sort_by_date {
+            rows.sort_by_key(|row| row.date);
+        }
+        let mut total = Balance::default();
+        for row in &mut rows {
+            for amount in row.amount.amounts() {
+                total.checked_add(&amount).ok_or(Overflow)?;
+            }
+            row.total = total.clone();
+        }
+        Ok(RegisterReport {
+            rows,
+            styles: journal.commodity_styles(),
+        })
+    }
+}
+
+impl fmt::Display for RegisterReport<'_> {
+    /// One line per row with the amount and running total right-aligned, and
+    /// continuation lines for balances in several commodities.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows: Vec<(&RegisterRow, Vec<String>, Vec<String>)> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let amount = format_balance(&self.styles, &row.amount);
+                (row, amount, format_balance(&self.styles, &row.total))
+            })
+            .collect();
+        let width = |texts: &mut dyn Iterator<Item = &str>| {
+            texts.map(|text| text.chars().count()).max().unwrap_or(0)
+        };
+        let payee = width(&mut self.rows.iter().map(|row| row.payee));
+        let account = width(&mut self.rows.iter().map(|row| row.account));
+        let amount = rows
+            .iter()
+            .flat_map(|(_, amount, _)| amount)
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let total = rows
+            .iter()
+            .flat_map(|(_, _, total)| total)
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (row, amounts, totals) in &rows {
+            for i in 0..amounts.len().max(totals.len()) {
+                let (date, payee_text, account_text) = match i {
+                    0 => (row.date.to_string(), row.payee, row.account),
+                    _ => (String::new(), "", ""),
+                };
+                let line = format!(
+                    "{:10}  {:payee$}  {:account$}  {:>amount$}  {:>total$}",
+                    date,
+                    payee_text,
+                    account_text,
+                    amounts.get(i).map_or("", String::as_str),
+                    totals.get(i).map_or("", String::as_str),
+                );
+                writeln!(f, "{}", line.trim_end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    const JOURNAL: &str = "2024-03-05 Landlord | Rent
+\tExpenses:Rent  USD 500
+\tAssets:Checking
+
+2024-03-01=2024-03-08 Groceries
+\tExpenses:Food  USD 40
+\tExpenses:Drink  USD 10
+\tAssets:Checking
+";
+
+    fn report(query: Query, options: RegisterOptions) -> String {
+        let mut journal = parse(JOURNAL).unwrap();
+        journal.balance().unwrap();
+        RegisterReport::new(&journal, &query, &options, None)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn register() {
+        assert_eq!(
+            report(Query::new(), RegisterOptions::new()),
+            "\
+2024-03-05  Landlord   Expenses:Rent     USD 500  USD 500
+2024-03-05  Landlord   Assets:Checking  USD -500        0
+2024-03-01  Groceries  Expenses:Food      USD 40   USD 40
+2024-03-01  Groceries  Expenses:Drink     USD 10   USD 50
+2024-03-01  Groceries  Assets:Checking   USD -50        0
+"
+        );
+    }
+
+    #[test]
+    fn register_options() {
+        assert_eq!(
+            report(
+                Query::new().tag("none").real(),
+                RegisterOptions::new().collapse()
+            ),
+            ""
+        );
+        let expenses = |options| {
+            let mut journal = parse(JOURNAL).unwrap();
+            journal.balance().unwrap();
+            let query = Query::new();
+            let report = RegisterReport::new(&journal, &query, &options, None).unwrap();
+            report
+                .rows
+                .into_iter()
+                .filter(|row| row.account != "Assets:Checking")
+                .map(|row| (row.date.to_string(), row.account, row.total.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            expenses(RegisterOptions::new().sort_by_date().auxillary_date()),
+            vec![
+                (
+                    "2024-03-05".to_string(),
+                    "Expenses:Rent",
+                    "USD 500".to_string()
+                ),
+                (
+                    "2024-03-08".to_string(),
+                    "Expenses:Food",
+                    "USD 40".to_string()
+                ),
+                (
+                    "2024-03-08".to_string(),
+                    "Expenses:Drink",
+                    "USD 50".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            expenses(RegisterOptions::new().sort_by_date().collapse()),
+            vec![
+                ("2024-03-01".to_string(), "<Total>", "0".to_string()),
+                ("2024-03-05".to_string(), "<Total>", "0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_overflowing_totals() {
+        let half = "USD 50000000000000000000000000000";
+        let source = format!("2024-03-01 Deposit\n\tAssets:A  {half}  ; :asset:\n\tIncome:A\n\n2024-03-02 Deposit\n\tAssets:B  {half}  ; :asset:\n\tIncome:B\n");
+        let mut journal = parse(&source).unwrap();
+        journal.balance().unwrap();
+        let query = Query::new().tag("asset");
+        assert_eq!(
+            RegisterReport::new(&journal, &query, &RegisterOptions::new(), None),
+            Err(Overflow)
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{include_paths, Builder, Journal, ParseError};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+/// A journal file and every file it includes, read into memory so that the
+/// parsed [`Journal`] can borrow from them.
+#[derive(Debug)]
+pub struct Sources {
+    /// The root file comes first.
+    files: Vec<Source>,
+}
+
+#[derive(Debug)]
+struct Source {
+    /// The path as given, or joined onto the directory of the including file.
+    path: PathBuf,
+    canonical: PathBuf,
+    text: String,
+    /// The files named by each `include` directive, keyed by the offset of
+    /// the directive's path in `text`.
+    includes: BTreeMap<usize, Vec<usize>>,
+}
+
+/// Why the files of a journal could not be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// An `include` path is not a valid glob pattern.
+    Pattern {
+        file: PathBuf,
+        line: usize,
+        pattern: String,
+    },
+    /// An `include` glob pattern matches no files.
+    NoMatch {
+        file: PathBuf,
+        line: usize,
+        pattern: String,
+    },
+    /// An `include` names a file that does not exist.
+    Missing {
+        file: PathBuf,
+        line: usize,
+        path: PathBuf,
+    },
+    /// A file includes itself, directly or through other files. Holds the
+    /// chain of includes, starting and ending with that file.
+    Cycle(Vec<PathBuf>),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            LoadError::Pattern {
+                file,
+                line,
+                pattern,
+            } => write!(
+                f,
+                "{}:{}: invalid include pattern {}",
+                file.display(),
+                line,
+                pattern
+            ),
+            LoadError::NoMatch {
+                file,
+                line,
+                pattern,
+            } => write!(
+                f,
+                "{}:{}: include {} matches no files",
+                file.display(),
+                line,
+                pattern
+            ),
+            LoadError::Missing { file, line, path } => write!(
+                f,
+                "{}:{}: included file {} does not exist",
+                file.display(),
+                line,
+                path.display()
+            ),
+            LoadError::Cycle(chain) => {
+                write!(f, "include cycle: ")?;
+                for (i, path) in chain.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "{}", path.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Whether `path` needs to be expanded as a glob pattern.
+fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// The files an `include` of `path` in `file` refers to. Relative paths are
+/// resolved against the including file's directory and patterns expand to
+/// the files they match, in alphabetical order.
+fn resolve(file: &Path, line: usize, path: &str) -> Result<Vec<PathBuf>, LoadError> {
+    let path = file.parent().unwrap_or(Path::new("")).join(path);
+    let pattern = path.to_string_lossy();
+    if !is_pattern(&pattern) {
+        return match path.exists() {
+            true => Ok(vec![path]),
+            false => Err(LoadError::Missing {
+                file: file.to_path_buf(),
+                line,
+                path,
+            }),
+        };
+    }
+    let error = |make: fn(PathBuf, usize, String) -> LoadError| {
+        make(file.to_path_buf(), line, pattern.to_string())
+    };
+    let paths: Vec<PathBuf> = glob::glob(&pattern)
+        .map_err(|_| {
+            error(|file, line, pattern| LoadError::Pattern {
+                file,
+                line,
+                pattern,
+            })
+        })?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+        .collect();
+    if paths.is_empty() {
+        return Err(error(|file, line, pattern| LoadError::NoMatch {
+            file,
+            line,
+            pattern,
+        }));
+    }
+    Ok(paths)
+}
+
+impl Sources {
+    /// Reads the journal at `path` and, recursively, every file it includes.
+    pub fn load(path: impl AsRef<Path>) -> Result<Sources, LoadError> {
+        let mut sources = Sources { files: Vec::new() };
+        sources.load_file(path.as_ref(), &mut Vec::new())?;
+        Ok(sources)
+    }
+
+    /// Reads `path` and the files it includes, returning its index in
+    /// `files`. `including` holds the canonical paths of the files that led
+    /// here, to detect cycles.
+    fn load_file(&mut self, path: &Path, including: &mut Vec<PathBuf>) -> Result<usize, LoadError> {
+        let io_error = |error| LoadError::Io {
+            path: path.to_path_buf(),
+            error,
+        };
+        let canonical = path.canonicalize().map_err(io_error)?;
+        if let Some(start) = including.iter().position(|p| *p == canonical) {
+            let mut chain = including[start..].to_vec();
+            chain.push(canonical);
+            return Err(LoadError::Cycle(chain));
+        }
+        // A file included from several places is only read once.
+        if let Some(index) = self.files.iter().position(|f| f.canonical == canonical) {
+            return Ok(index);
+        }
+        let text = fs::read_to_string(path).map_err(io_error)?;
+        let directives: Vec<(usize, usize, String)> = include_paths(&text)
+            .into_iter()
+            .map(|include| {
+                let offset = include.as_ptr() as usize - text.as_ptr() as usize;
+                let line = text[..offset].matches('\n').count() + 1;
+                (offset, line, include.to_string())
+            })
+            .collect();
+        let index = self.files.len();
+        self.files.push(Source {
+            path: path.to_path_buf(),
+            canonical: canonical.clone(),
+            text,
+            includes: BTreeMap::new(),
+        });
+
+        including.push(canonical);
+        for (offset, line, include) in directives {
+            let mut included = Vec::new();
+            for path in resolve(path, line, &include)? {
+                included.push(self.load_file(&path, including)?);
+            }
+            self.files[index].includes.insert(offset, included);
+        }
+        including.pop();
+        Ok(index)
+    }
+
+    /// Parses the journal like [`crate::parse_recovering`], reading the
+    /// entries of included files where they are included. Every item and
+    /// diagnostic records the file it came from.
+    pub fn parse_recovering(&self) -> (Journal<'_>, Vec<ParseError>) {
+        let mut builder = Builder::default();
+        let mut diagnostics = Vec::new();
+        self.parse_file(0, &mut builder, &mut diagnostics);
+        (builder.journal, diagnostics)
+    }
+
+    fn parse_file<'a>(
+        &'a self,
+        index: usize,
+        builder: &mut Builder<'a>,
+        diagnostics: &mut Vec<ParseError>,
+    ) {
+        let source = &self.files[index];
+        let mut errors = Vec::new();
+        let recover = |e: &crate::Error| {
+            errors.push(e.locate(&source.text).with_file(&source.path));
+            true
+        };
+        let include = |builder: &mut Builder<'a>, path: &'a str| {
+            let offset = path.as_ptr() as usize - source.text.as_ptr() as usize;
+            for &included in source.includes.get(&offset).into_iter().flatten() {
+                self.parse_file(included, builder, diagnostics);
+            }
+            Ok(())
+        };
+        let _ = builder.entries(&source.text, Some(&source.path), recover, include);
+        diagnostics.extend(errors);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn load_includes() {
+        let sources = Sources::load(fixture("include/main.ledger")).unwrap();
+        let (journal, diagnostics) = sources.parse_recovering();
+        assert_eq!(diagnostics, vec![]);
+        let files = |transactions: &[crate::Transaction]| {
+            transactions
+                .iter()
+                .map(|t| {
+                    let file = t.file.unwrap().strip_prefix(fixture("include")).unwrap();
+                    (file.to_str().unwrap().to_string(), t.line)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            files(&journal.transactions),
+            vec![
+                ("years/2023.ledger".to_string(), 1),
+                ("years/2024.ledger".to_string(), 3),
+                ("main.ledger".to_string(), 5),
+            ]
+        );
+        assert_eq!(
+            journal.accounts[0].file.unwrap(),
+            fixture("include/accounts.ledger")
+        );
+        // The alias declared in an included file applies to later files.
+        assert_eq!(
+            journal.transactions[0].postings[0].account.name,
+            "Expenses:Food"
+        );
+    }
+
+    #[test]
+    fn include_diagnostics_name_their_file() {
+        let sources = Sources::load(fixture("include/broken.ledger")).unwrap();
+        let (journal, diagnostics) = sources.parse_recovering();
+        assert_eq!(journal.transactions.len(), 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].file.as_deref(),
+            Some(fixture("include/bad.ledger").as_path())
+        );
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn load_crlf_with_bom() {
+        let sources = Sources::load(fixture("crlf/bank.ledger")).unwrap();
+        let (mut journal, diagnostics) = sources.parse_recovering();
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(journal.comments[0].text, "; Exported from the bank");
+        assert_eq!(
+            journal.comments[1].text,
+            "comment\r\nignored\r\nend comment"
+        );
+        assert_eq!(journal.accounts[0].note, Some("Everyday spending"));
+        assert_eq!(journal.accounts[0].line, 4);
+        assert!(journal.commodities[0].format.is_some());
+        assert_eq!(journal.prices[0].line, 11);
+        let transaction = &journal.transactions[0];
+        assert_eq!(transaction.merchant, Some("Grocer"));
+        assert_eq!(transaction.memo, "Weekly shop");
+        assert_eq!(transaction.comments, vec![":food:", "receipt: 7"]);
+        assert_eq!(transaction.postings[0].comments, vec!["big shop"]);
+        assert_eq!(transaction.postings[1].account.name, "Assets:Checking");
+        assert_eq!(transaction.postings[1].line, 20);
+        assert_eq!(journal.balance(), Ok(()));
+        assert_eq!(journal.check_assertions(), Ok(()));
+    }
+
+    #[test]
+    fn crlf_diagnostics() {
+        let sources = Sources::load(fixture("crlf/broken.ledger")).unwrap();
+        let (_, diagnostics) = sources.parse_recovering();
+        let located: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.line, d.column, d.snippet.as_str()))
+            .collect();
+        assert_eq!(
+            located,
+            vec![
+                (1, 1, "2024-02-30 Shop"),
+                (6, 23, "\tExpenses:Food  USD 20 x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_include_cycles() {
+        let error = Sources::load(fixture("include/cycle-a.ledger")).unwrap_err();
+        let LoadError::Cycle(chain) = error else {
+            panic!("expected a cycle, got {}", error);
+        };
+        let names: Vec<_> = chain
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["cycle-a.ledger", "cycle-b.ledger", "cycle-a.ledger"]
+        );
+    }
+
+    #[test]
+    fn reject_missing_includes() {
+        let error = Sources::load(fixture("include/missing.ledger")).unwrap_err();
+        assert!(matches!(error, LoadError::NoMatch { line: 2, .. }));
+        assert!(error
+            .to_string()
+            .ends_with("missing/*.ledger matches no files"));
+        let error = Sources::load(fixture("include/absent.ledger")).unwrap_err();
+        let LoadError::Missing { file, line, .. } = &error else {
+            panic!("expected a missing file, got {}", error);
+        };
+        assert_eq!(
+            (file.as_path(), *line),
+            (fixture("include/absent.ledger").as_path(), 3)
+        );
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:3: included file {} does not exist",
+                fixture("include/absent.ledger").display(),
+                fixture("include/nothere.ledger").display()
+            )
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{Amount, Cost, Journal, Posting};
+use std::fmt;
+use std::path::Path;
+
+/// A posting that strict mode rejects. See [`Journal::check_strict`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StrictError<'a> {
+    /// The posting's account has no `account` directive.
+    UndeclaredAccount {
+        file: Option<&'a Path>,
+        /// 1-based line of the posting.
+        line: usize,
+        account: &'a str,
+        /// The declared account with the most similar name, if any is close.
+        suggestion: Option<&'a str>,
+    },
+    /// An amount of the posting is in a commodity that has no `commodity`
+    /// directive.
+    UnknownCommodity {
+        file: Option<&'a Path>,
+        /// 1-based line of the posting.
+        line: usize,
+        commodity: &'a str,
+    },
+    /// The transaction's lines are indented with both tabs and spaces.
+    MixedIndentation {
+        file: Option<&'a Path>,
+        /// 1-based line of the transaction.
+        line: usize,
+    },
+}
+
+impl fmt::Display for StrictError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StrictError::UndeclaredAccount {
+                line,
+                account,
+                suggestion,
+                ..
+            } => {
+                write!(f, "line {}: account {} is not declared", line, account)?;
+                match suggestion {
+                    Some(suggestion) => write!(f, ", did you mean {}?", suggestion),
+                    None => Ok(()),
+                }
+            }
+            StrictError::UnknownCommodity {
+                line, commodity, ..
+            } => {
+                write!(f, "line {}: commodity {} is not declared", line, commodity)
+            }
+            StrictError::MixedIndentation { line, .. } => {
+                write!(f, "line {}: indentation mixes tabs and spaces", line)
+            }
+        }
+    }
+}
+
+impl<'a> StrictError<'a> {
+    /// The file of the offending posting.
+    pub fn file(&self) -> Option<&'a Path> {
+        match self {
+            StrictError::UndeclaredAccount { file, .. }
+            | StrictError::UnknownCommodity { file, .. }
+            | StrictError::MixedIndentation { file, .. } => *file,
+        }
+    }
+}
+
+impl std::error::Error for StrictError<'_> {}
+
+/// The number of single character insertions, deletions and substitutions
+/// needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(ca != *cb);
+            current.push(substitute.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The candidate closest to `name`, as long as at most a third of it has to
+/// change.
+fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance * 3 <= name.chars().count())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+impl<'a> Posting<'a> {
+    /// Every amount written on the posting: the amount itself, its lot and
+    /// `@` costs and its balance assertion.
+    fn amounts(&self) -> impl Iterator<Item = &Amount<'a>> {
+        let lot_cost = self.lot.as_ref().and_then(|lot| lot.cost.as_ref());
+        let costs = lot_cost
+            .into_iter()
+            .chain(&self.cost)
+            .map(|cost| match cost {
+                Cost::Unit(amount) | Cost::Total(amount) => amount,
+            });
+        let assertion = self.assertion.as_ref().map(|assertion| &assertion.amount);
+        self.amount.iter().chain(costs).chain(assertion)
+    }
+}
+
+impl<'a> Journal<'a> {
+    /// Checks that every posting is to an account declared with an `account`
+    /// directive, so a typo does not silently open a new account, and that
+    /// every amount is in a commodity declared with a `commodity` directive.
+    /// Transactions indented with both tabs and spaces are rejected too.
+    pub fn check_strict(&self) -> Result<(), Vec<StrictError<'a>>> {
+        let declared = || self.accounts.iter().map(|account| account.name);
+        let mut errors = Vec::new();
+        for transaction in &self.transactions {
+            let file = transaction.file;
+            if transaction.mixed_indentation {
+                errors.push(StrictError::MixedIndentation {
+                    file,
+                    line: transaction.line,
+                });
+            }
+            for posting in &transaction.postings {
+                let account = posting.account.name;
+                if !declared().any(|name| name == account) {
+                    errors.push(StrictError::UndeclaredAccount {
+                        file,
+                        line: posting.line,
+                        account,
+                        suggestion: closest(account, declared()),
+                    });
+                }
+                let mut unknown: Vec<&str> = Vec::new();
+                for amount in posting.amounts() {
+                    let commodity = amount.currency;
+                    if !unknown.contains(&commodity)
+                        && !self.commodities.iter().any(|c| c.symbol == commodity)
+                    {
+                        unknown.push(commodity);
+                        errors.push(StrictError::UnknownCommodity {
+                            file,
+                            line: posting.line,
+                            commodity,
+                        });
+                    }
+                }
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("Expenses:Fod", "Expenses:Food"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn undeclared_accounts() {
+        let journal = parse("account Expenses:Food\naccount Assets:Cash\ncommodity USD\n\n2024-03-01 Shop\n\tExpenses:Fod  USD 20\n\tAssets:Cash\n\tIncome:Salary  USD 0\n").unwrap();
+        let errors = journal.check_strict().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                "line 6: account Expenses:Fod is not declared, did you mean Expenses:Food?",
+                "line 8: account Income:Salary is not declared",
+            ]
+        );
+        let journal = parse("account Assets:Cash\naccount Equity\ncommodity USD\n\n2024-03-01 Open\n\tAssets:Cash  USD 20\n\tEquity\n").unwrap();
+        assert_eq!(journal.check_strict(), Ok(()));
+    }
+
+    #[test]
+    fn mixed_indentation() {
+        let journal = parse("account Assets:Cash\naccount Equity\ncommodity USD\n\n2024-03-01 Open\n    Assets:Cash  USD 20\n\tEquity\n\n2024-03-02 Spaces\n  Assets:Cash  USD 1\n    ; a deeper comment\n  Equity\n").unwrap();
+        assert_eq!(
+            journal.check_strict(),
+            Err(vec![StrictError::MixedIndentation {
+                file: None,
+                line: 5
+            }])
+        );
+    }
+
+    #[test]
+    fn unknown_commodities() {
+        let journal = parse("account Assets:Broker\naccount Assets:Cash\ncommodity USD\n\n2024-03-01 Buy\n\tAssets:Broker  10 AAPL {150 EUR} @ 160 EUR\n\tAssets:Cash  -1600 USD = USD 0\n").unwrap();
+        assert_eq!(
+            journal.check_strict(),
+            Err(vec![
+                StrictError::UnknownCommodity {
+                    file: None,
+                    line: 6,
+                    commodity: "AAPL"
+                },
+                StrictError::UnknownCommodity {
+                    file: None,
+                    line: 6,
+                    commodity: "EUR"
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use crate::Journal;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// How to read numbers whose decimal mark is ambiguous, such as `1,234`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NumberFormat {
+    /// The decimal mark to assume, as set by a `decimal-mark` directive. When
+    /// unset, a single `,` followed by exactly three digits groups thousands
+    /// and any other single mark is the decimal mark.
+    pub decimal_mark: Option<char>,
+}
+
+/// Which side of the number a commodity symbol is written on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// How an amount was written, so it can be displayed the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountStyle {
+    pub commodity_side: Side,
+    /// Whether the commodity is separated from the number by a space.
+    pub commodity_spaced: bool,
+    /// The character separating the integer part from the fraction.
+    pub decimal_mark: char,
+    /// The character separating groups of three digits, if any.
+    pub digit_group_mark: Option<char>,
+    /// The number of digits after the decimal mark.
+    pub precision: u32,
+}
+
+impl Default for AmountStyle {
+    fn default() -> Self {
+        AmountStyle {
+            commodity_side: Side::Left,
+            commodity_spaced: true,
+            decimal_mark: '.',
+            digit_group_mark: None,
+            precision: 0,
+        }
+    }
+}
+
+impl AmountStyle {
+    /// Writes `quantity` of `commodity` in this style, rounding it to the
+    /// style's precision, e.g. `USD -1,234.50` or `1.234,5 EUR`.
+    pub fn format(&self, commodity: &str, quantity: Decimal) -> String {
+        let rounded = quantity
+            .round_dp_with_strategy(self.precision, RoundingStrategy::MidpointAwayFromZero)
+            .abs();
+        let digits = format!("{:.*}", self.precision as usize, rounded);
+        let (integer, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+        let mut number = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                number.extend(self.digit_group_mark);
+            }
+            number.push(digit);
+        }
+        if !fraction.is_empty() {
+            number.push(self.decimal_mark);
+            number.push_str(fraction);
+        }
+        if quantity.is_sign_negative() && !rounded.is_zero() {
+            number.insert(0, '-');
+        }
+
+        // Names the commodity parser would not read back whole need quotes.
+        let bare = matches!(crate::commodity(commodity), Ok(("", name)) if name == commodity);
+        let symbol = match bare {
+            true => commodity.to_string(),
+            false => format!("\"{}\"", commodity),
+        };
+        let space = if self.commodity_spaced { " " } else { "" };
+        match self.commodity_side {
+            Side::Left => format!("{}{}{}", symbol, space, number),
+            Side::Right => format!("{}{}{}", number, space, symbol),
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Reads a number recognised by [`crate::util::number`], returning its
+    /// exact value and how it was written, or `None` if its marks are
+    /// inconsistent, e.g. `1.234.5` or `1,23`.
+    pub(crate) fn read(&self, number: &str) -> Option<(Decimal, AmountStyle)> {
+        let number = number.replace('_', "");
+        if number.contains(['e', 'E']) {
+            let value = Decimal::from_scientific(&number).ok()?;
+            let style = AmountStyle {
+                precision: value.scale(),
+                ..AmountStyle::default()
+            };
+            return Some((value, style));
+        }
+
+        let marks: Vec<(usize, char)> = number
+            .char_indices()
+            .filter(|(_, c)| matches!(c, '.' | ','))
+            .collect();
+        let decimal_mark = match marks.as_slice() {
+            [] => None,
+            // With both marks present, the last one must be the decimal mark.
+            [.., (_, last)] if marks.iter().any(|(_, c)| c != last) => Some(*last),
+            [(i, mark)] => match self.decimal_mark {
+                Some(decimal_mark) => Some(*mark).filter(|&mark| mark == decimal_mark),
+                None if *mark == ',' && number.len() - i - 1 == 3 => None,
+                None => Some(*mark),
+            },
+            // The same mark repeated can only be grouping digits.
+            _ => None,
+        };
+        let digit_group_mark = marks
+            .iter()
+            .map(|&(_, c)| c)
+            .find(|&c| Some(c) != decimal_mark);
+        if digit_group_mark.is_some() && digit_group_mark == self.decimal_mark {
+            return None;
+        }
+
+        let (integer, fraction) = match decimal_mark {
+            Some(mark) => number.rsplit_once(mark)?,
+            None => (number.as_str(), ""),
+        };
+        if fraction.contains(['.', ',']) {
+            return None;
+        }
+        if let Some(group) = digit_group_mark {
+            let mut groups = integer.split(group);
+            groups.next();
+            if groups.any(|digits| digits.len() != 3) {
+                return None;
+            }
+        }
+        let mut digits: String = integer.chars().filter(char::is_ascii_digit).collect();
+        if digits.is_empty() {
+            digits.push('0');
+        }
+        if !fraction.is_empty() {
+            digits.push('.');
+            digits.push_str(fraction);
+        }
+        let value = Decimal::from_str(&digits).ok()?;
+        let style = AmountStyle {
+            decimal_mark: decimal_mark.unwrap_or(self.decimal_mark.unwrap_or('.')),
+            digit_group_mark,
+            precision: fraction.len() as u32,
+            ..AmountStyle::default()
+        };
+        Some((value, style))
+    }
+
+    /// `style` with its marks swapped if need be, so that amounts written in
+    /// it are read back the same under this format.
+    pub(crate) fn readable(&self, style: AmountStyle) -> AmountStyle {
+        let decimal_mark = self.decimal_mark.unwrap_or('.');
+        if style.decimal_mark == decimal_mark {
+            return style;
+        }
+        let group = if decimal_mark == ',' { '.' } else { ',' };
+        AmountStyle {
+            decimal_mark,
+            digit_group_mark: style.digit_group_mark.map(|_| group),
+            ..style
+        }
+    }
+}
+
+impl<'a> Journal<'a> {
+    /// The display style of every commodity. A `format` given in a
+    /// `commodity` directive wins; otherwise the style is inferred from how
+    /// the commodity's amounts are written: the symbol placement and marks
+    /// of its first amount and the largest precision of any of them.
+    pub fn commodity_styles(&self) -> BTreeMap<&'a str, AmountStyle> {
+        let mut styles: BTreeMap<&'a str, AmountStyle> = BTreeMap::new();
+        let amounts = self
+            .transactions
+            .iter()
+            .flat_map(|t| &t.postings)
+            .filter_map(|p| p.amount.as_ref());
+        for amount in amounts {
+            styles
+                .entry(amount.currency)
+                .and_modify(|style| style.precision = style.precision.max(amount.style.precision))
+                .or_insert(amount.style);
+        }
+        for declaration in &self.commodities {
+            if let Some(format) = declaration.format {
+                styles.insert(declaration.symbol, format);
+            }
+        }
+        styles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn read(format: NumberFormat, number: &str) -> Option<(Decimal, AmountStyle)> {
+        format.read(number)
+    }
+
+    fn style(decimal_mark: char, digit_group_mark: Option<char>, precision: u32) -> AmountStyle {
+        AmountStyle {
+            decimal_mark,
+            digit_group_mark,
+            precision,
+            ..AmountStyle::default()
+        }
+    }
+
+    #[test]
+    fn read_numbers() {
+        let default = NumberFormat::default();
+        assert_eq!(
+            read(default, "1,234.56"),
+            Some((Decimal::new(123456, 2), style('.', Some(','), 2)))
+        );
+        assert_eq!(
+            read(default, "1.234,56"),
+            Some((Decimal::new(123456, 2), style(',', Some('.'), 2)))
+        );
+        assert_eq!(
+            read(default, "1,234,567"),
+            Some((Decimal::new(1234567, 0), style('.', Some(','), 0)))
+        );
+        assert_eq!(
+            read(default, "1,234"),
+            Some((Decimal::new(1234, 0), style('.', Some(','), 0)))
+        );
+        assert_eq!(
+            read(default, "12,5"),
+            Some((Decimal::new(125, 1), style(',', None, 1)))
+        );
+        assert_eq!(
+            read(default, "1.234"),
+            Some((Decimal::new(1234, 3), style('.', None, 3)))
+        );
+        assert_eq!(read(default, "1.234.5"), None);
+        assert_eq!(read(default, "1,234.5,6"), None);
+    }
+
+    #[test]
+    fn read_numbers_with_decimal_mark() {
+        let comma = NumberFormat {
+            decimal_mark: Some(','),
+        };
+        assert_eq!(
+            read(comma, "1.234"),
+            Some((Decimal::new(1234, 0), style(',', Some('.'), 0)))
+        );
+        assert_eq!(
+            read(comma, "1,234"),
+            Some((Decimal::new(1234, 3), style(',', None, 3)))
+        );
+        assert_eq!(read(comma, "1,234.56"), None);
+    }
+
+    #[test]
+    fn infer_commodity_styles() {
+        let journal = parse("2024-03-01 Shop\n\tExpenses:Food  EUR 1.234,5\n\tExpenses:Food  EUR 2,25\n\tExpenses:Food  USD 1,000\n\tAssets:Cash\n").unwrap();
+        let styles = journal.commodity_styles();
+        assert_eq!(
+            styles["EUR"],
+            AmountStyle {
+                commodity_side: Side::Left,
+                commodity_spaced: true,
+                ..style(',', Some('.'), 2)
+            }
+        );
+        assert_eq!(styles["USD"], style('.', Some(','), 0));
+
+        let journal = parse("commodity USD\n\tformat 1,000.00 USD\n\n2024-03-01 Shop\n\tExpenses:Food  USD 12\n\tAssets:Cash\n").unwrap();
+        assert_eq!(
+            journal.commodity_styles()["USD"],
+            AmountStyle {
+                commodity_side: Side::Right,
+                ..style('.', Some(','), 2)
+            }
+        );
+    }
+
+    #[test]
+    fn format_amounts() {
+        let dollars = AmountStyle {
+            commodity_spaced: false,
+            ..style('.', Some(','), 2)
+        };
+        assert_eq!(
+            dollars.format("$", Decimal::new(-1234567, 1)),
+            "$-123,456.70"
+        );
+        assert_eq!(dollars.format("$", Decimal::new(-1, 3)), "$0.00");
+        assert_eq!(dollars.format("$", Decimal::new(5, 3)), "$0.01");
+        let euros = AmountStyle {
+            commodity_side: Side::Right,
+            ..style(',', Some('.'), 1)
+        };
+        assert_eq!(euros.format("EUR", Decimal::new(12345, 0)), "12.345,0 EUR");
+        assert_eq!(
+            style('.', None, 0).format("Air Miles", Decimal::new(100, 0)),
+            "\"Air Miles\" 100"
+        );
+    }
+}
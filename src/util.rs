@@ -1,13 +1,14 @@
 use nom::{
     branch::alt,
-    character::complete::{char, one_of, space1},
-    combinator::{opt, recognize},
+    character::complete::{char, digit1, one_of, space0, space1},
+    combinator::{not, opt, recognize, verify},
     multi::{many0, many1},
-    sequence::{preceded, terminated, tuple},
-    IResult,
+    sequence::{pair, preceded, terminated, tuple},
 };
 
-pub fn float(input: &str) -> IResult<&str, &str> {
+use crate::IResult;
+
+pub fn float(input: &str) -> IResult<'_, &str> {
     alt((
         // Case one: .42
         recognize(tuple((
@@ -26,13 +27,30 @@ pub fn float(input: &str) -> IResult<&str, &str> {
     ))(input)
 }
 
-fn decimal(input: &str) -> IResult<&str, &str> {
+/// The digits of an amount, without sign. They may be split into groups by
+/// `,` or `.`, which of those is the decimal mark is left to
+/// [`crate::NumberFormat`], or be written in scientific notation.
+pub fn number(input: &str) -> IResult<'_, &str> {
+    alt((
+        verify(float, |n: &str| n.contains(['e', 'E'])),
+        recognize(tuple((
+            decimal,
+            many0(pair(one_of(",."), decimal)),
+            opt(terminated(one_of(",."), not(digit1))),
+        ))),
+        recognize(pair(one_of(",."), decimal)),
+    ))(input)
+}
+
+fn decimal(input: &str) -> IResult<'_, &str> {
     recognize(many1(terminated(one_of("0123456789"), many0(char('_')))))(input)
 }
 
-pub fn space2(input: &str) -> IResult<&str, ()> {
-    let (input, _) = char(' ')(input)?;
-    let (input, _) = space1(input)?;
+/// Two spaces or a tab, and any whitespace after them, as separate an
+/// account from its amount.
+pub fn space2(input: &str) -> IResult<'_, ()> {
+    let (input, _) = alt((recognize(pair(char(' '), space1)), recognize(char('\t'))))(input)?;
+    let (input, _) = space0(input)?;
     Ok((input, ()))
 }
 
@@ -40,7 +58,7 @@ pub fn space2(input: &str) -> IResult<&str, ()> {
 mod test {
     use super::*;
 
-    fn test_and_extract<'a, T, F: Fn(&'a str) -> IResult<&'a str, T>>(input: &'a str, f: F) -> T {
+    fn test_and_extract<'a, T, F: Fn(&'a str) -> IResult<'a, T>>(input: &'a str, f: F) -> T {
         let (_, out) = f(input).unwrap();
         out
     }
@@ -54,6 +72,16 @@ mod test {
         assert_eq!("42.42", test_and_extract("42.42", float));
     }
 
+    #[test]
+    fn parse_number() {
+        assert_eq!("1,234.56", test_and_extract("1,234.56 USD", number));
+        assert_eq!("1.234,56", test_and_extract("1.234,56", number));
+        assert_eq!("42.", test_and_extract("42. USD", number));
+        assert_eq!(".5", test_and_extract(".5", number));
+        assert_eq!("1.5E3", test_and_extract("1.5E3", number));
+        assert_eq!("20", test_and_extract("20EUR", number));
+    }
+
     #[test]
     fn parse_decimal() {
         assert_eq!("123", test_and_extract("123", decimal));
@@ -64,5 +92,8 @@ mod test {
     fn parse_space2() {
         assert_eq!((), test_and_extract("  ", space2));
         assert_eq!((), test_and_extract("         ", space2));
+        assert_eq!((), test_and_extract("\t", space2));
+        assert_eq!((), test_and_extract(" \t ", space2));
+        assert!(space2(" USD").is_err());
     }
 }
@@ -0,0 +1,194 @@
+use crate::{Amount, Balance, Journal, Overflow, Posting, PriceDb, Transaction};
+use chrono::NaiveDate;
+
+/// When to price amounts in a report's target commodity, like ledger's
+/// `-B`, `-V`/`-X` and `--value` options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Valuation {
+    /// What was paid: each posting's `@` or lot cost, see
+    /// [`Posting::weight`], converted on the posting's date if the cost is in
+    /// another commodity.
+    Cost,
+    /// Market prices on the last day of the report.
+    End,
+    /// Market prices on the date of each posting.
+    Then,
+    /// Market prices on a fixed date.
+    At(NaiveDate),
+}
+
+impl<'a> PriceDb<'a> {
+    /// `balance` converted into `target` at the prices of `date`. Amounts in
+    /// a commodity without a price are kept as they are. Fails if the total
+    /// gets too large to represent.
+    pub fn value(
+        &self,
+        balance: &Balance<'a>,
+        target: &'a str,
+        date: NaiveDate,
+    ) -> Result<Balance<'a>, Overflow> {
+        let mut valued = Balance::default();
+        for amount in balance.amounts() {
+            let amount = self.convert(&amount, target, date).unwrap_or(amount);
+            valued.checked_add(&amount).ok_or(Overflow)?;
+        }
+        Ok(valued)
+    }
+}
+
+/// Values postings in a target commodity.
+#[derive(Debug, Clone)]
+pub struct Valuer<'a> {
+    prices: PriceDb<'a>,
+    target: &'a str,
+    valuation: Valuation,
+    end: NaiveDate,
+}
+
+impl<'a> Valuer<'a> {
+    /// Values in `target` using the journal's prices. The report is taken to
+    /// end on the journal's last posting or price date; see [`Valuer::end`].
+    pub fn new(journal: &Journal<'a>, target: &'a str, valuation: Valuation) -> Self {
+        let postings = journal.transactions.iter().flat_map(|t| {
+            t.postings
+                .iter()
+                .map(move |posting| posting.effective_date(t))
+        });
+        let prices = journal.prices.iter().map(|price| price.date);
+        let end = postings.chain(prices).max().unwrap_or_default();
+        Valuer {
+            prices: journal.price_db(),
+            target,
+            valuation,
+            end,
+        }
+    }
+
+    /// Sets the last day of the report, for [`Valuation::End`].
+    pub fn end(mut self, date: NaiveDate) -> Self {
+        self.end = date;
+        self
+    }
+
+    /// The value of `posting`, or its amount unchanged when there is no
+    /// price to convert it with. `None` if the posting has no amount. Fails
+    /// if its cost is too large to represent.
+    pub fn posting(
+        &self,
+        transaction: &Transaction,
+        posting: &Posting<'a>,
+    ) -> Result<Option<Amount<'a>>, Overflow> {
+        let Some(amount) = posting.amount.clone() else {
+            return Ok(None);
+        };
+        let date = posting.effective_date(transaction);
+        let (amount, date) = match self.valuation {
+            Valuation::Cost => (posting.weight().ok_or(Overflow)?, date),
+            Valuation::End => (amount, self.end),
+            Valuation::Then => (amount, date),
+            Valuation::At(at) => (amount, at),
+        };
+        Ok(Some(
+            self.prices
+                .convert(&amount, self.target, date)
+                .unwrap_or(amount),
+        ))
+    }
+
+    /// The total value of `postings`. Fails if a cost or the total gets too
+    /// large to represent.
+    pub fn postings<'j>(
+        &self,
+        postings: impl IntoIterator<Item = (&'j Transaction<'a>, &'j Posting<'a>)>,
+    ) -> Result<Balance<'a>, Overflow>
+    where
+        'a: 'j,
+    {
+        let mut total = Balance::default();
+        for (transaction, posting) in postings {
+            if let Some(value) = self.posting(transaction, posting)? {
+                total.checked_add(&value).ok_or(Overflow)?;
+            }
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse, Query};
+    use rust_decimal::Decimal;
+
+    const JOURNAL: &str = "P 2024-01-01 EUR 1.10 USD
+P 2024-03-01 AAPL 150 USD
+P 2024-06-01 AAPL 200 USD
+
+2024-03-01 Buy
+\tAssets:Broker  10 AAPL @ 140 USD
+\tAssets:Cash
+
+2024-04-01 Deposit
+\tAssets:Bank  EUR 100
+\tEquity
+";
+
+    /// The value of everything but cash, which must all be in dollars.
+    fn assets(journal: &Journal, valuer: &Valuer) -> Decimal {
+        let query = Query::new();
+        let postings = journal
+            .postings(&query)
+            .filter(|(_, p)| p.account.name != "Assets:Cash" && p.account.name != "Equity");
+        let value = valuer.postings(postings).unwrap();
+        assert!(value.amounts().all(|amount| amount.currency == "USD"));
+        value.get("USD")
+    }
+
+    #[test]
+    fn value_postings() {
+        let journal = parse(JOURNAL).unwrap();
+        let value = |valuation| assets(&journal, &Valuer::new(&journal, "USD", valuation));
+        assert_eq!(value(Valuation::Cost), Decimal::new(1510, 0));
+        assert_eq!(value(Valuation::End), Decimal::new(2110, 0));
+        assert_eq!(value(Valuation::Then), Decimal::new(1610, 0));
+        let march = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        assert_eq!(value(Valuation::At(march)), Decimal::new(1610, 0));
+        let valuer = Valuer::new(&journal, "USD", Valuation::End).end(march);
+        assert_eq!(assets(&journal, &valuer), Decimal::new(1610, 0));
+    }
+
+    #[test]
+    fn keep_amounts_without_prices() {
+        let journal = parse(JOURNAL).unwrap();
+        let mut balance = Balance::default();
+        balance.add(&Amount::new("AAPL", Decimal::new(2, 0)));
+        balance.add(&Amount::new("GBP", Decimal::new(5, 0)));
+        let date = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+        assert_eq!(
+            journal
+                .price_db()
+                .value(&balance, "USD", date)
+                .unwrap()
+                .to_string(),
+            "AAPL 2, GBP 5"
+        );
+    }
+
+    #[test]
+    fn reject_overflowing_values() {
+        let half = "USD 50000000000000000000000000000";
+        let source = format!("2024-03-01 Deposit\n\tAssets:A  {half}\n\tIncome:A\n\n2024-03-02 Deposit\n\tAssets:B  {half}\n\tIncome:B\n\n2024-03-03 Buy\n\tAssets:Broker  79228162514264337593543950335 AAPL @ 2 USD\n\tAssets:Cash  USD -1\n");
+        let journal = parse(&source).unwrap();
+        let valuer = Valuer::new(&journal, "USD", Valuation::Cost);
+        let query = Query::new();
+        let assets = |prefix| {
+            let postings = journal
+                .postings(&query)
+                .filter(move |(_, p)| p.account.name.starts_with(prefix));
+            valuer.postings(postings)
+        };
+        assert_eq!(assets("Assets:"), Err(Overflow));
+        assert_eq!(assets("Assets:Broker"), Err(Overflow));
+        assert!(assets("Income").is_ok());
+    }
+}